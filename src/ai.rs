@@ -0,0 +1,245 @@
+use std::collections::{HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Arena, GameRules, GameState, InputQueue, Position, SnakeState};
+
+/// Decides which way a player's snake turns on the next tick.
+pub trait SnakeController: Send + Sync {
+    fn next_direction(&mut self, game: &GameState, player: usize) -> SnakeState;
+}
+
+/// The keyboard, through the turns queued up by the player.
+impl SnakeController for InputQueue {
+    fn next_direction(&mut self, game: &GameState, player: usize) -> SnakeState {
+        self.next(game.snakes[player].heading)
+    }
+}
+
+/// The directions a snake can turn towards without dying on the next tick, of which a snake with
+/// no segments left has none.
+fn safe_directions(game: &GameState, player: usize) -> impl Iterator<Item = SnakeState> + '_ {
+    let snake = &game.snakes[player];
+
+    SnakeState::ALL.into_iter().filter(move |&direction| {
+        !snake.segments.is_empty()
+            && direction != snake.heading.opposite()
+            && game.is_free(game.next_head(snake, direction))
+    })
+}
+
+/// The number of free tiles that can be reached from `start`, including itself.
+fn reachable_tiles(game: &GameState, start: Position) -> usize {
+    let mut visited = HashSet::new();
+    let mut frontier = vec![start];
+
+    visited.insert(start);
+
+    while let Some(position) = frontier.pop() {
+        for direction in SnakeState::ALL {
+            let next = game.neighbour(position, direction);
+
+            if game.is_free(next) && visited.insert(next) {
+                frontier.push(next);
+            }
+        }
+    }
+
+    visited.len()
+}
+
+/// The first turn of a shortest path from the snake's head to `target` through free tiles.
+fn first_step_towards(game: &GameState, player: usize, target: Position) -> Option<SnakeState> {
+    let snake = &game.snakes[player];
+    let mut visited = HashSet::new();
+    let mut frontier = VecDeque::new();
+
+    visited.insert(*snake.segments.first()?);
+
+    for direction in safe_directions(game, player) {
+        let next = game.next_head(snake, direction);
+
+        if visited.insert(next) {
+            frontier.push_back((next, direction));
+        }
+    }
+
+    while let Some((position, first_step)) = frontier.pop_front() {
+        if position == target {
+            return Some(first_step);
+        }
+
+        for direction in SnakeState::ALL {
+            let next = game.neighbour(position, direction);
+
+            if game.is_free(next) && visited.insert(next) {
+                frontier.push_back((next, first_step));
+            }
+        }
+    }
+
+    None
+}
+
+/// Takes the shortest path to the food, or heads for the most open space when there is none.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GreedyBot;
+
+impl SnakeController for GreedyBot {
+    fn next_direction(&mut self, game: &GameState, player: usize) -> SnakeState {
+        let snake = &game.snakes[player];
+
+        game.food
+            .and_then(|food| first_step_towards(game, player, food.position))
+            .or_else(|| {
+                safe_directions(game, player).max_by_key(|&direction| {
+                    reachable_tiles(game, game.next_head(snake, direction))
+                })
+            })
+            .unwrap_or(snake.heading)
+    }
+}
+
+/// Follows a fixed cycle through every tile of the arena, which is slow to reach the food but can
+/// fill the whole board. Falls back to `GreedyBot` whenever the next tile of the cycle is taken.
+#[derive(Clone, Debug)]
+pub struct HamiltonianBot {
+    arena: Arena,
+    /// The tile after each tile in the cycle, indexed by `x + y * width`.
+    successors: Vec<Position>,
+}
+
+impl HamiltonianBot {
+    /// Builds the cycle, which only exists for arenas without walls that have an even number of
+    /// tiles.
+    pub fn new(rules: &GameRules) -> Option<Self> {
+        let Arena(width, height) = rules.arena;
+
+        if !rules.walls.is_empty() || width < 2 || height < 2 {
+            return None;
+        }
+
+        // The cycle zigzags along the rows, skipping the first column, which it then takes back
+        // to the start. This needs an even number of rows, so the arena is turned on its side when
+        // only the number of columns is even.
+        let (columns, rows, transposed) = match (width % 2, height % 2) {
+            (_, 0) => (width as i32, height as i32, false),
+            (0, _) => (height as i32, width as i32, true),
+            _ => return None,
+        };
+
+        let mut cycle = Vec::with_capacity((width * height) as usize);
+
+        for row in 0..rows {
+            if row % 2 == 0 {
+                cycle.extend((1..columns).map(|column| (column, row)));
+            } else {
+                cycle.extend((1..columns).rev().map(|column| (column, row)));
+            }
+        }
+
+        cycle.extend((0..rows).rev().map(|row| (0, row)));
+
+        let cycle = cycle
+            .into_iter()
+            .map(|(column, row)| match transposed {
+                false => Position(column, row),
+                true => Position(row, column),
+            })
+            .collect::<Vec<Position>>();
+
+        let mut successors = vec![Position(0, 0); cycle.len()];
+
+        for (index, &position) in cycle.iter().enumerate() {
+            successors[(position.0 + position.1 * width as i32) as usize] =
+                cycle[(index + 1) % cycle.len()];
+        }
+
+        Some(HamiltonianBot {
+            arena: rules.arena,
+            successors,
+        })
+    }
+}
+
+impl SnakeController for HamiltonianBot {
+    fn next_direction(&mut self, game: &GameState, player: usize) -> SnakeState {
+        let head = game.snakes[player].head();
+        let successor = self
+            .successors
+            .get((head.0 + head.1 * self.arena.0 as i32) as usize)
+            .copied();
+
+        safe_directions(game, player)
+            .find(|&direction| Some(game.neighbour(head, direction)) == successor)
+            .unwrap_or_else(|| GreedyBot.next_direction(game, player))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BotKind {
+    Greedy,
+    Hamiltonian,
+}
+
+impl BotKind {
+    /// A bot of this kind for the given rules, using `GreedyBot` instead of a `HamiltonianBot` on
+    /// arenas without a Hamiltonian cycle.
+    pub fn controller(self, rules: &GameRules) -> Box<dyn SnakeController> {
+        match self {
+            BotKind::Greedy => Box::new(GreedyBot),
+            BotKind::Hamiltonian => match HamiltonianBot::new(rules) {
+                Some(bot) => Box::new(bot),
+                None => Box::new(GreedyBot),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(width: u32, height: u32) -> GameRules {
+        GameRules {
+            arena: Arena(width, height),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hamiltonian_cycles_visit_every_tile_once() {
+        for &(width, height) in &[(2, 2), (4, 4), (5, 4), (4, 5), (6, 3), (3, 6), (10, 7)] {
+            let bot = HamiltonianBot::new(&rules(width, height)).expect("the arena has a cycle");
+            let mut visited = HashSet::new();
+            let mut position = Position(0, 0);
+
+            for _ in 0..width * height {
+                assert!(visited.insert(position), "{:?} is visited twice", position);
+
+                let next = bot.successors[(position.0 + position.1 * width as i32) as usize];
+                let step = next - position;
+
+                assert_eq!(step.0.abs() + step.1.abs(), 1);
+                assert!(next.0 < width as i32 && next.1 < height as i32);
+                position = next;
+            }
+
+            assert_eq!(position, Position(0, 0));
+            assert_eq!(visited.len(), (width * height) as usize);
+        }
+    }
+
+    #[test]
+    fn hamiltonian_cycles_need_an_even_arena_without_walls() {
+        assert!(HamiltonianBot::new(&rules(5, 5)).is_none());
+        assert!(HamiltonianBot::new(&rules(1, 4)).is_none());
+
+        let walled = GameRules {
+            walls: vec![Position(1, 1)],
+            ..rules(4, 4)
+        };
+        assert!(HamiltonianBot::new(&walled).is_none());
+    }
+}
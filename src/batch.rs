@@ -0,0 +1,245 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{
+    env::{EnvConfig, SnakeEnv, StepInfo},
+    game::SnakeState,
+};
+
+struct Slot {
+    env: SnakeEnv,
+    /// Where the seed of each new episode comes from, so the whole batch can be reproduced.
+    seeds: StdRng,
+}
+
+/// Many independent `SnakeEnv`s stepped in parallel across every core. A game that ends is reset
+/// straight away, so its observation is already the first one of its next episode, while its
+/// reward, done flag and info still describe the step that ended it.
+pub struct BatchEnv {
+    slots: Vec<Slot>,
+    observation_shape: Vec<usize>,
+    /// The observations of every game one after the other, each `observation_size` long.
+    observations: Vec<f32>,
+    rewards: Vec<f32>,
+    dones: Vec<bool>,
+    infos: Vec<Option<StepInfo>>,
+}
+
+impl BatchEnv {
+    /// Creates `count` games sharing the same config, where game `i` draws the seeds of its
+    /// episodes from a generator seeded with `seed + i`.
+    pub fn new(config: EnvConfig, count: usize, seed: u64) -> Self {
+        let observation_shape = config.observation_shape();
+        let slots = (0..count)
+            .map(|index| Slot {
+                env: SnakeEnv::new(config.clone()),
+                seeds: StdRng::seed_from_u64(seed.wrapping_add(index as u64)),
+            })
+            .collect::<Vec<Slot>>();
+        let observation_size = observation_shape.iter().product::<usize>();
+
+        let mut batch = BatchEnv {
+            slots,
+            observation_shape,
+            observations: vec![0.0; count * observation_size],
+            rewards: vec![0.0; count],
+            dones: vec![false; count],
+            infos: vec![None; count],
+        };
+
+        batch.reset();
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn observation_shape(&self) -> &[usize] {
+        &self.observation_shape
+    }
+
+    pub fn observation_size(&self) -> usize {
+        self.observation_shape.iter().product()
+    }
+
+    /// Starts a new episode in every game, returning all of their observations.
+    pub fn reset(&mut self) -> &[f32] {
+        let size = self.observation_size();
+
+        self.slots
+            .par_iter_mut()
+            .zip(self.observations.par_chunks_mut(size))
+            .for_each(|(slot, observation)| {
+                let seed = slot.seeds.gen();
+
+                slot.env.start_episode(seed);
+                slot.env.observe_into(observation);
+            });
+
+        self.rewards.fill(0.0);
+        self.dones.fill(false);
+        self.infos.fill(None);
+
+        &self.observations
+    }
+
+    /// Steps every game with its own action, resetting those that end.
+    pub fn step(&mut self, actions: &[SnakeState]) {
+        assert_eq!(
+            actions.len(),
+            self.slots.len(),
+            "expected one action per game"
+        );
+
+        let size = self.observation_size();
+
+        (
+            self.slots.par_iter_mut(),
+            self.observations.par_chunks_mut(size),
+            self.rewards.par_iter_mut(),
+            self.dones.par_iter_mut(),
+            self.infos.par_iter_mut(),
+            actions.par_iter(),
+        )
+            .into_par_iter()
+            .for_each(|(slot, observation, reward, done, info, &action)| {
+                let (step_reward, step_done, step_info) = slot.env.advance(action);
+
+                if step_done {
+                    let seed = slot.seeds.gen();
+                    slot.env.start_episode(seed);
+                }
+
+                slot.env.observe_into(observation);
+                *reward = step_reward;
+                *done = step_done;
+                *info = Some(step_info);
+            });
+    }
+
+    pub fn observations(&self) -> &[f32] {
+        &self.observations
+    }
+
+    pub fn rewards(&self) -> &[f32] {
+        &self.rewards
+    }
+
+    pub fn dones(&self) -> &[bool] {
+        &self.dones
+    }
+
+    /// The info of each game's last step, or `None` if it hasn't stepped since the last reset.
+    pub fn infos(&self) -> &[Option<StepInfo>] {
+        &self.infos
+    }
+
+    pub fn env(&self, index: usize) -> &SnakeEnv {
+        &self.slots[index].env
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        env::ObservationKind,
+        game::{Arena, DeathCause, GameRules, Position, StepOutcome},
+    };
+
+    /// Games on an 8 by 6 arena whose snakes start at (6, 3) heading right, so going straight on
+    /// crashes into the edge on the second step.
+    fn config() -> EnvConfig {
+        EnvConfig {
+            rules: GameRules {
+                arena: Arena(8, 6),
+                spawns: vec![Position(6, 3)],
+                ..Default::default()
+            },
+            observation: ObservationKind::Grid,
+            ..Default::default()
+        }
+    }
+
+    /// Plays every game of `batch` through the same turns, different for each game, collecting
+    /// everything they return.
+    fn play(batch: &mut BatchEnv, steps: usize) -> Vec<(Vec<f32>, Vec<f32>, Vec<bool>)> {
+        let turns = [
+            SnakeState::Up,
+            SnakeState::Left,
+            SnakeState::Down,
+            SnakeState::Right,
+        ];
+
+        (0..steps)
+            .map(|step| {
+                let actions = (0..batch.len())
+                    .map(|index| turns[(step / 3 + index) % turns.len()])
+                    .collect::<Vec<SnakeState>>();
+                batch.step(&actions);
+
+                (
+                    batch.observations().to_vec(),
+                    batch.rewards().to_vec(),
+                    batch.dones().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn games_that_end_are_reset_straight_away() {
+        let mut batch = BatchEnv::new(config(), 3, 0);
+
+        batch.step(&[SnakeState::Right; 3]);
+        assert_eq!(batch.dones(), &[false; 3]);
+
+        batch.step(&[SnakeState::Right; 3]);
+        assert_eq!(batch.dones(), &[true; 3]);
+
+        for index in 0..batch.len() {
+            let info = batch.infos()[index].unwrap();
+            assert_eq!(info.outcome, StepOutcome::Died(DeathCause::Wall));
+            assert_eq!(info.steps, 2);
+
+            let snake = &batch.env(index).game().snakes[0];
+            assert!(snake.is_alive());
+            assert_eq!(snake.segments[0], Position(6, 3));
+        }
+    }
+
+    #[test]
+    fn observations_are_laid_out_one_game_after_another() {
+        let mut batch = BatchEnv::new(config(), 4, 0);
+        play(&mut batch, 10);
+
+        let size = batch.observation_size();
+        assert_eq!(size, 4 * 6 * 8);
+        assert_eq!(batch.observations().len(), 4 * size);
+
+        for index in 0..batch.len() {
+            assert_eq!(
+                &batch.observations()[index * size..(index + 1) * size],
+                batch.env(index).observe().data.as_slice()
+            );
+        }
+    }
+
+    #[test]
+    fn the_same_seed_plays_the_same_games_on_any_number_of_threads() {
+        let run = |threads: usize| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+                .install(|| play(&mut BatchEnv::new(config(), 8, 42), 50))
+        };
+
+        assert_eq!(run(1), run(4));
+    }
+}
@@ -0,0 +1,353 @@
+//! Runs a game for clients to join over the network with `snake --connect <address>`. The server
+//! owns the game, taking a free player slot for every client, so run it with `--set players=<n>`
+//! for more than one of them. Spectators join with `snake --watch <address>`, taking no slot. Listens
+//! on `--bind`, `0.0.0.0:7777` by default.
+
+use std::{collections::VecDeque, io::ErrorKind, net::TcpListener, time::Duration};
+
+use bevy::{app::ScheduleRunnerSettings, log::LogPlugin, prelude::*};
+use snake::{
+    cli,
+    net::{ClientMessage, MessageStream, ServerMessage, Snapshot, DEFAULT_PORT, PROTOCOL_VERSION},
+    DeathCause, GameRules, GameState, HeadlessSnakePlugin, InputQueue, Player, SnakeConfig,
+};
+
+struct Client {
+    stream: MessageStream,
+    name: String,
+    /// The slot taken by the client, once it has said hello.
+    player: Option<usize>,
+    /// Set for spectators, once they have asked to watch.
+    watching: bool,
+    /// The number of the newest turn received from the client.
+    received: u64,
+    /// The numbers of the client's turns waiting in its slot's input queue, oldest first.
+    queued: VecDeque<u64>,
+    /// Set when sending to the client failed, so it gets dropped.
+    failed: bool,
+}
+
+struct Server {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    tick: u64,
+}
+
+impl Client {
+    /// Whether the client has been welcomed, as a player or as a spectator.
+    fn is_welcome(&self) -> bool {
+        self.player.is_some() || self.watching
+    }
+}
+
+impl Server {
+    fn broadcast(&mut self, message: &ServerMessage) {
+        let line = message.encode();
+
+        for client in self.clients.iter_mut().filter(|client| client.is_welcome()) {
+            client.stream.send(&line);
+        }
+    }
+
+    /// Tells a newly welcomed client who is already playing.
+    fn introduce(&mut self, index: usize) {
+        let introductions = self
+            .clients
+            .iter()
+            .filter_map(|client| {
+                client.player.map(|player| ServerMessage::Joined {
+                    player,
+                    name: client.name.clone(),
+                })
+            })
+            .collect::<Vec<ServerMessage>>();
+
+        for message in introductions {
+            self.clients[index].stream.send(&message.encode());
+        }
+    }
+}
+
+fn accept_clients(mut server: ResMut<Server>) {
+    loop {
+        match server.listener.accept() {
+            Ok((stream, address)) => match MessageStream::new(stream) {
+                Ok(stream) => {
+                    info!("{} connected", address);
+                    server.clients.push(Client {
+                        stream,
+                        name: address.to_string(),
+                        player: None,
+                        watching: false,
+                        received: 0,
+                        queued: VecDeque::new(),
+                        failed: false,
+                    });
+                }
+                Err(error) => warn!("Failed to set up the connection to {}: {}", address, error),
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) => {
+                warn!("Failed to accept a connection: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+fn handle_messages(
+    mut server: ResMut<Server>,
+    config: Res<SnakeConfig>,
+    rules: Res<GameRules>,
+    game: Res<GameState>,
+    mut slots: Query<(&Player, &mut InputQueue)>,
+) {
+    let mut index = 0;
+
+    while index < server.clients.len() {
+        let mut leaving = server.clients[index].failed;
+        let lines = server.clients[index]
+            .stream
+            .receive()
+            .unwrap_or_else(|error| {
+                info!("{} disconnected: {}", server.clients[index].name, error);
+                leaving = true;
+                Vec::new()
+            });
+
+        for line in lines {
+            match ClientMessage::decode(&line) {
+                Ok(ClientMessage::Hello { version, name })
+                    if !server.clients[index].is_welcome() =>
+                {
+                    let taken = server
+                        .clients
+                        .iter()
+                        .filter_map(|client| client.player)
+                        .collect::<Vec<usize>>();
+                    // Only slots steered by an input queue are for people, the rest are bots.
+                    let free = slots
+                        .iter_mut()
+                        .map(|(player, _)| player.0)
+                        .filter(|player| !taken.contains(player))
+                        .min();
+
+                    let reply = match (version == PROTOCOL_VERSION, free) {
+                        (false, _) => Err(format!(
+                            "the server speaks protocol version {}",
+                            PROTOCOL_VERSION
+                        )),
+                        (true, None) => Err(String::from("the game is full")),
+                        (true, Some(player)) => Ok(player),
+                    };
+
+                    let client = &mut server.clients[index];
+
+                    match reply {
+                        Ok(player) => {
+                            info!("{} joined as player {}", name, player + 1);
+                            client.name = name.clone();
+                            client.stream.send(
+                                &ServerMessage::Welcome {
+                                    version: PROTOCOL_VERSION,
+                                    player: Some(player),
+                                    tick_interval: config.tick_interval,
+                                    rules: rules.clone(),
+                                }
+                                .encode(),
+                            );
+
+                            server.introduce(index);
+                            server.clients[index].player = Some(player);
+                            server.broadcast(&ServerMessage::Joined { player, name });
+                        }
+                        Err(reason) => {
+                            info!("Refused {}: {}", client.name, reason);
+                            client.stream.send(&ServerMessage::Refused(reason).encode());
+                            let _ = client.stream.flush();
+                            leaving = true;
+                        }
+                    }
+                }
+                Ok(ClientMessage::Watch { version, name })
+                    if !server.clients[index].is_welcome() =>
+                {
+                    let client = &mut server.clients[index];
+
+                    if version == PROTOCOL_VERSION {
+                        info!("{} is watching", name);
+                        client.name = name;
+                        client.watching = true;
+                        client.stream.send(
+                            &ServerMessage::Welcome {
+                                version: PROTOCOL_VERSION,
+                                player: None,
+                                tick_interval: config.tick_interval,
+                                rules: rules.clone(),
+                            }
+                            .encode(),
+                        );
+
+                        server.introduce(index);
+                    } else {
+                        let reason =
+                            format!("the server speaks protocol version {}", PROTOCOL_VERSION);
+                        info!("Refused {}: {}", client.name, reason);
+                        client.stream.send(&ServerMessage::Refused(reason).encode());
+                        let _ = client.stream.flush();
+                        leaving = true;
+                    }
+                }
+                Ok(ClientMessage::Hello { .. } | ClientMessage::Watch { .. }) => {}
+                Ok(ClientMessage::Turn {
+                    sequence,
+                    direction,
+                }) => {
+                    let client = &mut server.clients[index];
+                    client.received = client.received.max(sequence);
+
+                    for (slot, mut queue) in slots.iter_mut() {
+                        if Some(slot.0) == client.player {
+                            if let Some(snake) = game.snakes.get(slot.0) {
+                                if queue.push(direction, snake.heading) {
+                                    client.queued.push_back(sequence);
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(ClientMessage::Ping(id)) => server.clients[index]
+                    .stream
+                    .send(&ServerMessage::Pong(id).encode()),
+                Ok(ClientMessage::Bye) => leaving = true,
+                Err(error) => warn!(
+                    "Ignoring a message from {}: {}",
+                    server.clients[index].name, error
+                ),
+            }
+        }
+
+        if leaving {
+            let client = server.clients.remove(index);
+
+            if let Some(player) = client.player {
+                info!("{} left", client.name);
+                server.broadcast(&ServerMessage::Left { player });
+            } else if client.watching {
+                info!("{} stopped watching", client.name);
+            }
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Takes the snakes of empty slots off the board, and brings back those of clients that joined.
+fn fill_slots(
+    server: Res<Server>,
+    mut game: ResMut<GameState>,
+    mut slots: Query<(&Player, &mut InputQueue)>,
+) {
+    for (player, mut queue) in slots.iter_mut() {
+        let taken = server
+            .clients
+            .iter()
+            .any(|client| client.player == Some(player.0));
+        let death = match game.snakes.get(player.0) {
+            Some(snake) => snake.death,
+            None => continue,
+        };
+
+        if !taken && death != Some(DeathCause::Left) {
+            game.remove(player.0);
+        } else if taken && death == Some(DeathCause::Left) {
+            // Waits for the spawn to clear up, without touching the game until then.
+            if let Some(snake) = game.spawn_snake(player.0) {
+                game.snakes[player.0] = snake;
+                queue.clear();
+            }
+        }
+    }
+}
+
+fn send_snapshots(
+    mut server: ResMut<Server>,
+    game: Res<GameState>,
+    slots: Query<(&Player, &InputQueue)>,
+) {
+    // Whatever left a slot's queue since the last frame was either fed into a tick or cleared.
+    for client in server.clients.iter_mut() {
+        let waiting = slots
+            .iter()
+            .find(|(player, _)| Some(player.0) == client.player)
+            .map_or(0, |(_, queue)| queue.len());
+
+        while client.queued.len() > waiting {
+            client.queued.pop_front();
+        }
+    }
+
+    if game.is_changed() {
+        server.tick += 1;
+
+        let tick = server.tick;
+
+        for client in server
+            .clients
+            .iter_mut()
+            .filter(|client| client.is_welcome())
+        {
+            // Everything up to the oldest turn still queued has been applied or dropped.
+            let ack = client
+                .queued
+                .front()
+                .map_or(client.received, |sequence| sequence - 1);
+
+            client
+                .stream
+                .send(&ServerMessage::Snapshot(Snapshot::new(tick, ack, &game)).encode());
+        }
+    }
+
+    for client in server.clients.iter_mut() {
+        if let Err(error) = client.stream.flush() {
+            info!("Failed to send to {}: {}", client.name, error);
+            client.failed = true;
+        }
+    }
+}
+
+fn main() {
+    let config = cli::config();
+    let rules = config
+        .rules()
+        .unwrap_or_else(|error| panic!("Failed to load the level: {}", error));
+
+    let address =
+        cli::argument_value("--bind").unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+    let listener = TcpListener::bind(&address)
+        .unwrap_or_else(|error| panic!("Failed to listen on {}: {}", address, error));
+    listener
+        .set_nonblocking(true)
+        .expect("Failed to set up the listener");
+
+    App::build()
+        // Checks for messages often, while the game itself still moves at `tick_interval`.
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_millis(5)))
+        .insert_resource(cli::rng())
+        .insert_resource(config)
+        .insert_resource(rules)
+        .insert_resource(Server {
+            listener,
+            clients: Vec::new(),
+            tick: 0,
+        })
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        .add_plugin(HeadlessSnakePlugin)
+        .add_system(accept_clients.system())
+        .add_system(handle_messages.system())
+        .add_system(fill_slots.system())
+        .add_system(send_snapshots.system())
+        .run();
+}
@@ -0,0 +1,296 @@
+//! Plays the game in a terminal, for when there is no window to open, such as over SSH.
+
+use std::{
+    io::{self, stdout, Write},
+    time::Duration,
+};
+
+use bevy::{app::AppExit, app::ScheduleRunnerSettings, prelude::*};
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{self, Event, KeyModifiers},
+    execute, queue,
+    style::{self, Print, ResetColor},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use snake::{
+    cli, AppState, Bot, Connection, GameResults, GameState, HeadlessSnakePlugin, HighScores,
+    InputQueue, Player, Position, ReplayPlayer, Score, SnakeConfig, SnakeState, Theme,
+};
+
+const KEYS: [[(event::KeyCode, SnakeState); 4]; 2] = [
+    [
+        (event::KeyCode::Left, SnakeState::Left),
+        (event::KeyCode::Right, SnakeState::Right),
+        (event::KeyCode::Up, SnakeState::Up),
+        (event::KeyCode::Down, SnakeState::Down),
+    ],
+    [
+        (event::KeyCode::Char('a'), SnakeState::Left),
+        (event::KeyCode::Char('d'), SnakeState::Right),
+        (event::KeyCode::Char('w'), SnakeState::Up),
+        (event::KeyCode::Char('s'), SnakeState::Down),
+    ],
+];
+
+/// Puts the terminal back the way it was when dropped, even if the game panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen, Hide)?;
+
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The results of the last game, shown until the next one starts.
+#[derive(Default)]
+struct Results(String);
+
+fn read_keys(
+    game: Res<GameState>,
+    connection: Option<Res<Connection>>,
+    mut state: ResMut<State<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    mut players: Query<(&Player, &mut InputQueue)>,
+) {
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        let key = match event::read() {
+            Ok(Event::Key(key)) => key,
+            _ => continue,
+        };
+
+        match (key.code, state.current()) {
+            (event::KeyCode::Char('q') | event::KeyCode::Esc, _) => exit.send(AppExit),
+            (event::KeyCode::Char('c'), _) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                exit.send(AppExit)
+            }
+            (event::KeyCode::Char(' '), AppState::Menu | AppState::GameOver) => {
+                let _ = state.set(AppState::Playing);
+            }
+            // A server keeps moving the snakes whatever we do, so its games can't be paused.
+            (event::KeyCode::Char('p'), AppState::Playing) if connection.is_none() => {
+                let _ = state.push(AppState::Paused);
+            }
+            (event::KeyCode::Char('p'), AppState::Paused) => {
+                let _ = state.pop();
+            }
+            (code, AppState::Playing) => {
+                for (player, mut queue) in players.iter_mut() {
+                    if let (Some(keys), Some(snake)) =
+                        (KEYS.get(player.0), game.snakes.get(player.0))
+                    {
+                        for &(key, direction) in keys {
+                            if key == code {
+                                queue.push(direction, snake.heading);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn record_results(
+    config: Res<SnakeConfig>,
+    game: Res<GameState>,
+    score: Res<Score>,
+    replay: Option<Res<ReplayPlayer>>,
+    bots: Query<&Player, With<Bot>>,
+    mut results: ResMut<Results>,
+) {
+    // A table that fails to load is never saved over, so the scores already in it aren't lost. The
+    // error is shown with the results, as printing it would only mess up the screen.
+    let (path, mut scores, load_error) = match HighScores::default_path() {
+        Some(path) => match HighScores::load(&path) {
+            Ok(scores) => (Some(path), scores, None),
+            Err(error) => {
+                let message = format!(
+                    "Failed to load high scores from {}, so new ones won't be saved: {}\n",
+                    path.display(),
+                    error
+                );
+                (None, HighScores::default(), Some(message))
+            }
+        },
+        None => (None, HighScores::default(), None),
+    };
+
+    let game_results = GameResults::new(&config, &game, &score.eaten, |index| {
+        bots.iter().any(|player| player.0 == index)
+    });
+
+    // A replay was scored when it was recorded, so watching it again only shows the table.
+    if replay.is_none() {
+        game_results.record(&mut scores);
+
+        if let Some(path) = &path {
+            // Printing an error would only mess up the screen, so a failed save is left at that.
+            let _ = scores.save(path);
+        }
+    }
+
+    let mut contents = game_results.summary();
+    contents.push_str(&game_results.table(&scores));
+    contents.extend(load_error);
+    contents.push_str("\nPress Space to restart, Q to quit");
+    results.0 = contents;
+}
+
+fn terminal_colour([red, green, blue]: [f32; 3]) -> style::Color {
+    style::Color::Rgb {
+        r: (red * 255.0) as u8,
+        g: (green * 255.0) as u8,
+        b: (blue * 255.0) as u8,
+    }
+}
+
+fn draw(
+    theme: Res<Theme>,
+    game: Res<GameState>,
+    score: Res<Score>,
+    state: Res<State<AppState>>,
+    results: Res<Results>,
+    connection: Option<Res<Connection>>,
+) {
+    if !game.is_changed() && !state.is_changed() && !results.is_changed() {
+        return;
+    }
+
+    let arena = game.rules.arena;
+    let (width, height) = (arena.0 as usize, arena.1 as usize);
+
+    // Every tile is two characters wide, so the arena looks roughly square.
+    let mut tiles = vec![("  ", style::Color::Reset); width * height];
+    let mut set = |x: i32, y: i32, tile: (&'static str, style::Color)| {
+        if game.in_bounds(Position(x, y)) {
+            tiles[(height - 1 - y as usize) * width + x as usize] = tile;
+        }
+    };
+
+    for wall in &game.rules.walls {
+        set(wall.0, wall.1, ("▒▒", terminal_colour(theme.wall)));
+    }
+
+    if let Some(food) = game.food {
+        set(
+            food.position.0,
+            food.position.1,
+            ("<>", terminal_colour(theme.food_colour(food.kind))),
+        );
+    }
+
+    for (player, snake) in game.snakes.iter().enumerate() {
+        let body = if snake.is_alive() { "██" } else { "░░" };
+
+        for (index, segment) in snake.segments.iter().enumerate() {
+            let colour = theme.segment_colour(player, index, snake.segments.len());
+            set(segment.0, segment.1, (body, terminal_colour(colour)));
+        }
+    }
+
+    let mut lines = vec![format!("┌{}┐", "─".repeat(width * 2))];
+
+    for row in tiles.chunks(width) {
+        let mut line = String::from("│");
+
+        for &(text, colour) in row {
+            line.push_str(&format!("{}{}", style::SetForegroundColor(colour), text));
+        }
+
+        line.push_str(&format!("{}│", ResetColor));
+        lines.push(line);
+    }
+
+    lines.push(format!("└{}┘", "─".repeat(width * 2)));
+
+    let mut hud = String::new();
+
+    for (index, snake) in game.snakes.iter().enumerate() {
+        if game.snakes.len() > 1 {
+            hud.push_str(&format!("P{} ", index + 1));
+        }
+
+        hud.push_str(&format!(
+            "Score: {}  Length: {}  ",
+            score.eaten.get(index).copied().unwrap_or(0),
+            snake.segments.len()
+        ));
+    }
+
+    lines.push(format!("{}Time: {:.0}s", hud, score.elapsed));
+    lines.push(String::new());
+
+    let message = match state.current() {
+        AppState::Menu if connection.is_some() => {
+            "Snake!\n\nPlayer 1: arrow keys, Player 2: WASD\nQ to quit\n\nPress Space to start"
+        }
+        AppState::Menu => {
+            "Snake!\n\nPlayer 1: arrow keys, Player 2: WASD\nP to pause, Q to quit\n\nPress Space to start"
+        }
+        AppState::Paused => "Paused\n\nPress P to resume",
+        AppState::GameOver => results.0.as_str(),
+        AppState::Playing => "",
+    };
+
+    lines.extend(message.lines().map(String::from));
+
+    let mut out = stdout();
+
+    if state.is_changed() || results.is_changed() {
+        let _ = queue!(out, Clear(ClearType::All));
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let _ = queue!(out, MoveTo(0, row as u16), Print(line));
+    }
+
+    let _ = out.flush();
+}
+
+fn main() {
+    let config = cli::config();
+    let rules = config
+        .rules()
+        .unwrap_or_else(|error| panic!("Failed to load the level: {}", error));
+    let theme = config
+        .theme()
+        .unwrap_or_else(|error| panic!("Failed to load the theme: {}", error));
+
+    let broadcast = cli::broadcast();
+    let guard = TerminalGuard::new().expect("Failed to set up the terminal");
+    let mut app = App::build();
+
+    if let Some(broadcast) = broadcast {
+        app.insert_resource(broadcast);
+    }
+
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+        1.0 / 60.0,
+    )))
+    .insert_resource(cli::rng())
+    .insert_resource(config)
+    .insert_resource(rules)
+    .insert_resource(theme)
+    .init_resource::<Results>()
+    .add_plugins(MinimalPlugins)
+    .add_state(AppState::Menu)
+    .add_plugin(HeadlessSnakePlugin)
+    .add_system(read_keys.system())
+    .add_system(draw.system())
+    .add_system_set(SystemSet::on_enter(AppState::GameOver).with_system(record_results.system()))
+    .run();
+
+    drop(guard);
+}
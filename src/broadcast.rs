@@ -0,0 +1,160 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpListener, ToSocketAddrs},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    net::{ClientMessage, MessageStream, ServerMessage, Snapshot, PROTOCOL_VERSION},
+    results::player_name,
+    Bot, GameState, Player, SnakeConfig,
+};
+
+struct Watcher {
+    stream: MessageStream,
+    name: String,
+    /// Set once the spectator has asked to watch.
+    watching: bool,
+}
+
+/// Lets spectators watch a local game with `snake --watch <address>`, speaking the same protocol as
+/// `snake-server` but turning away anyone asking to play. Inserting one before adding
+/// `HeadlessSnakePlugin` or `SnakeActionPlugin` starts the broadcast.
+pub struct Broadcast {
+    listener: TcpListener,
+    watchers: Vec<Watcher>,
+    tick: u64,
+}
+
+impl Broadcast {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Broadcast {
+            listener,
+            watchers: Vec::new(),
+            tick: 0,
+        })
+    }
+}
+
+fn accept_watchers(broadcast: &mut Broadcast) {
+    loop {
+        match broadcast.listener.accept() {
+            Ok((stream, address)) => match MessageStream::new(stream) {
+                Ok(stream) => broadcast.watchers.push(Watcher {
+                    stream,
+                    name: address.to_string(),
+                    watching: false,
+                }),
+                Err(error) => warn!("Failed to set up the connection to {}: {}", address, error),
+            },
+            Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+            Err(error) => {
+                warn!("Failed to accept a spectator: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+pub(crate) fn broadcast_game(
+    mut broadcast: ResMut<Broadcast>,
+    config: Res<SnakeConfig>,
+    game: Res<GameState>,
+    bots: Query<&Player, With<Bot>>,
+) {
+    accept_watchers(&mut broadcast);
+
+    let snapshot = match game.is_changed() {
+        true => {
+            broadcast.tick += 1;
+            Some(ServerMessage::Snapshot(Snapshot::new(broadcast.tick, 0, &game)).encode())
+        }
+        false => None,
+    };
+
+    let introductions = || {
+        (0..game.snakes.len()).map(|player| {
+            let is_bot = bots.iter().any(|bot| bot.0 == player);
+
+            ServerMessage::Joined {
+                player,
+                name: player_name(&config, player, is_bot),
+            }
+        })
+    };
+
+    broadcast.watchers.retain_mut(|watcher| {
+        let lines = match watcher.stream.receive() {
+            Ok(lines) => lines,
+            Err(error) => {
+                info!("{} stopped watching: {}", watcher.name, error);
+                return false;
+            }
+        };
+
+        for line in lines {
+            match ClientMessage::decode(&line) {
+                Ok(ClientMessage::Watch { version, name }) if !watcher.watching => {
+                    if version != PROTOCOL_VERSION {
+                        watcher.stream.send(
+                            &ServerMessage::Refused(format!(
+                                "the game speaks protocol version {}",
+                                PROTOCOL_VERSION
+                            ))
+                            .encode(),
+                        );
+                        let _ = watcher.stream.flush();
+                        return false;
+                    }
+
+                    info!("{} is watching", name);
+                    watcher.name = name;
+                    watcher.watching = true;
+                    watcher.stream.send(
+                        &ServerMessage::Welcome {
+                            version: PROTOCOL_VERSION,
+                            player: None,
+                            tick_interval: config.tick_interval,
+                            rules: game.rules.clone(),
+                        }
+                        .encode(),
+                    );
+
+                    for introduction in introductions() {
+                        watcher.stream.send(&introduction.encode());
+                    }
+                }
+                Ok(ClientMessage::Hello { .. }) => {
+                    watcher.stream.send(
+                        &ServerMessage::Refused(String::from("this game only takes spectators"))
+                            .encode(),
+                    );
+                    let _ = watcher.stream.flush();
+                    return false;
+                }
+                Ok(ClientMessage::Bye) => {
+                    info!("{} stopped watching", watcher.name);
+                    return false;
+                }
+                Ok(_) => {}
+                Err(error) => warn!("Ignoring a message from {}: {}", watcher.name, error),
+            }
+        }
+
+        if let (true, Some(snapshot)) = (watcher.watching, &snapshot) {
+            watcher.stream.send(snapshot);
+        }
+
+        match watcher.stream.flush() {
+            Ok(()) => true,
+            Err(error) => {
+                info!("Failed to send to {}: {}", watcher.name, error);
+                false
+            }
+        }
+    });
+}
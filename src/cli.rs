@@ -0,0 +1,66 @@
+//! Command line handling shared by the binaries.
+
+use std::path::PathBuf;
+
+use crate::{Broadcast, GameRng, SnakeConfig};
+
+pub fn argument_value(name: &str) -> Option<String> {
+    std::env::args()
+        .skip_while(|argument| argument != name)
+        .nth(1)
+}
+
+pub fn argument_values(name: &str) -> Vec<String> {
+    std::env::args()
+        .collect::<Vec<String>>()
+        .windows(2)
+        .filter(|pair| pair[0] == name)
+        .map(|pair| pair[1].clone())
+        .collect()
+}
+
+pub fn has_flag(name: &str) -> bool {
+    std::env::args().any(|argument| argument == name)
+}
+
+/// Reads the config given by `--config`, applying every `--set key=value` on top.
+pub fn config() -> SnakeConfig {
+    let mut config = match argument_value("--config") {
+        Some(path) => SnakeConfig::load(&PathBuf::from(&path))
+            .unwrap_or_else(|error| panic!("Failed to load config {}: {}", path, error)),
+        None => SnakeConfig::default(),
+    };
+
+    for setting in argument_values("--set") {
+        let (key, value) = setting
+            .split_once('=')
+            .expect("--set expects a key=value pair");
+
+        config
+            .set(key, value)
+            .unwrap_or_else(|error| panic!("Invalid setting {}: {}", setting, error));
+    }
+
+    // Hands every snake over to the computer, for an attract mode or just to watch.
+    if has_flag("--demo") {
+        config.bots = config.players;
+    }
+
+    config
+}
+
+/// Lets spectators watch the game on `--broadcast <address>`, if given.
+pub fn broadcast() -> Option<Broadcast> {
+    argument_value("--broadcast").map(|address| {
+        Broadcast::bind(&address)
+            .unwrap_or_else(|error| panic!("Failed to listen on {}: {}", address, error))
+    })
+}
+
+/// A generator seeded by `--seed`, or a random one.
+pub fn rng() -> GameRng {
+    match argument_value("--seed") {
+        Some(seed) => GameRng::new(seed.parse().expect("--seed must be an unsigned integer")),
+        None => GameRng::default(),
+    }
+}
@@ -0,0 +1,634 @@
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    net::{ClientMessage, MessageStream, ServerMessage, Snapshot, PROTOCOL_VERSION},
+    update_axis, AppState, GameCamera, GameRng, GameRules, GameState, InputBinding, InputQueue,
+    Player, Score, SnakeAction, SnakeConfig, SnakeSegments, SnakeState, INPUT_BINDINGS,
+};
+
+/// A connection to a `snake-server`, or to a game being broadcast. Inserting one before adding
+/// `SnakeActionPlugin` plays or watches the game there instead of simulating it locally.
+pub struct Connection {
+    stream: MessageStream,
+    player: Option<usize>,
+    tick_interval: f64,
+    rules: GameRules,
+    /// Lines that arrived along with the welcome, still to be handled.
+    backlog: Vec<String>,
+}
+
+impl Connection {
+    /// Connects and joins the game, waiting a few seconds at most for the server to let us in.
+    pub fn connect(address: impl ToSocketAddrs, name: &str) -> io::Result<Self> {
+        Self::open(
+            address,
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: name.to_owned(),
+            },
+        )
+    }
+
+    /// Connects as a spectator, who sees the game without a snake of their own.
+    pub fn watch(address: impl ToSocketAddrs, name: &str) -> io::Result<Self> {
+        Self::open(
+            address,
+            ClientMessage::Watch {
+                version: PROTOCOL_VERSION,
+                name: name.to_owned(),
+            },
+        )
+    }
+
+    fn open(address: impl ToSocketAddrs, hello: ClientMessage) -> io::Result<Self> {
+        let mut stream = MessageStream::new(TcpStream::connect(address)?)?;
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        stream.send(&hello.encode());
+
+        while Instant::now() < deadline {
+            stream.flush()?;
+
+            let mut lines = stream.receive()?.into_iter();
+
+            while let Some(line) = lines.next() {
+                match ServerMessage::decode(&line)? {
+                    ServerMessage::Welcome {
+                        version,
+                        player,
+                        tick_interval,
+                        rules,
+                    } if version == PROTOCOL_VERSION => {
+                        return Ok(Connection {
+                            stream,
+                            player,
+                            tick_interval,
+                            rules,
+                            backlog: lines.collect(),
+                        })
+                    }
+                    ServerMessage::Welcome { version, .. } => {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("the server speaks protocol version {}", version),
+                        ))
+                    }
+                    ServerMessage::Refused(reason) => {
+                        return Err(io::Error::new(ErrorKind::ConnectionRefused, reason))
+                    }
+                    _ => {}
+                }
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Err(io::Error::new(
+            ErrorKind::TimedOut,
+            "the server didn't answer",
+        ))
+    }
+
+    /// The index of our snake in `GameState::snakes`, or `None` when watching.
+    pub fn player(&self) -> Option<usize> {
+        self.player
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    /// See `MessageStream::set_delay`.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.stream.set_delay(delay);
+    }
+}
+
+/// The two newest snapshots, the older one being where other snakes are drawn sliding from.
+#[derive(Default)]
+pub struct Snapshots {
+    previous: Option<Snapshot>,
+    latest: Option<Snapshot>,
+    /// Seconds since the latest snapshot arrived.
+    since_latest: f32,
+}
+
+/// Our guess at the game as the server will have it once our latest turns reach it, so that our own
+/// snake answers the keyboard right away instead of a round trip later.
+struct Prediction {
+    player: usize,
+    /// Turns sent to the server that the latest snapshot doesn't include yet, oldest first.
+    pending: VecDeque<(u64, SnakeState)>,
+    next_sequence: u64,
+    /// The pending turns the prediction hasn't fed into a tick yet.
+    queue: InputQueue,
+    /// The latest snapshot, moved ahead by about a round trip with the pending turns.
+    game: GameState,
+    /// Moves the prediction on by itself when a snapshot is late.
+    timer: Timer,
+    /// A smoothed measure of the round trip to the server, in seconds.
+    round_trip: f64,
+    ping_timer: Timer,
+    pings_sent: u64,
+    /// The ping waiting for its pong, with when it was sent.
+    ping: Option<(u64, Instant)>,
+}
+
+impl Prediction {
+    /// Moves the prediction on by one tick, following the same rules as `move_snake` with every
+    /// other snake carrying on straight.
+    fn step(&mut self, rng: &mut GameRng) {
+        let mut directions = self
+            .game
+            .snakes
+            .iter()
+            .map(|snake| snake.heading)
+            .collect::<Vec<SnakeState>>();
+
+        if let Some(snake) = self.game.snakes.get(self.player) {
+            if snake.is_alive() {
+                directions[self.player] = self.queue.next(snake.heading);
+            }
+        }
+
+        self.game.step(&directions, rng);
+    }
+
+    /// Rewinds the prediction to a new snapshot, then replays the turns the server hadn't seen.
+    fn rewind(&mut self, snapshot: &Snapshot, tick_interval: f64, rng: &mut GameRng) {
+        self.pending
+            .retain(|&(sequence, _)| sequence > snapshot.ack);
+        snapshot.apply(&mut self.game);
+
+        self.queue.clear();
+
+        if let Some(snake) = self.game.snakes.get(self.player) {
+            let mut heading = snake.heading;
+
+            for &(_, direction) in &self.pending {
+                if self.queue.push(direction, heading) {
+                    heading = direction;
+                }
+            }
+        }
+
+        // Snapshots are half a round trip old when they arrive, and our turns are half a round trip
+        // away from the server, so that is how far ahead of the server our snake has to be.
+        let lead = (self.round_trip / tick_interval).round() as u32;
+
+        for _ in 0..lead {
+            self.step(rng);
+        }
+
+        self.timer.reset();
+    }
+}
+
+/// Present when watching a game rather than playing in it.
+pub(crate) struct Spectator {
+    /// The player the camera follows, or `None` to show the whole arena.
+    pub(crate) following: Option<usize>,
+    /// The names the server announced for each player.
+    pub(crate) names: Vec<Option<String>>,
+}
+
+fn setup_client(
+    mut commands: Commands,
+    connection: Res<Connection>,
+    rules: Res<GameRules>,
+    mut rng: ResMut<GameRng>,
+) {
+    // Shows the snakes where they start until the first snapshot arrives.
+    let game = GameState::new(rules.clone(), &mut *rng);
+
+    for index in 0..game.snakes.len() {
+        let mut player = commands.spawn();
+
+        player.insert(Player(index));
+
+        if Some(index) == connection.player {
+            player.insert(InputBinding(INPUT_BINDINGS[0]));
+        }
+    }
+
+    if let Some(player) = connection.player {
+        commands.insert_resource(Prediction {
+            player,
+            pending: VecDeque::new(),
+            next_sequence: 1,
+            queue: InputQueue::default(),
+            game: game.clone(),
+            timer: Timer::from_seconds(connection.tick_interval as f32, true),
+            round_trip: 0.0,
+            ping_timer: Timer::from_seconds(1.0, true),
+            pings_sent: 0,
+            ping: None,
+        });
+    } else {
+        commands.insert_resource(Spectator {
+            following: None,
+            names: vec![None; game.snakes.len()],
+        });
+    }
+
+    commands.insert_resource(game);
+}
+
+fn send_turns(
+    input: Res<Input<KeyCode>>,
+    mut connection: ResMut<Connection>,
+    mut prediction: ResMut<Prediction>,
+    bindings: Query<&InputBinding>,
+) {
+    for binding in bindings.iter() {
+        for &(key, direction) in &binding.0 {
+            let heading = match prediction.game.snakes.get(prediction.player) {
+                Some(snake) => snake.heading,
+                None => continue,
+            };
+
+            // Turns the server would drop are dropped here already, as they would only be undone.
+            if input.just_pressed(key) && prediction.queue.push(direction, heading) {
+                let sequence = prediction.next_sequence;
+                prediction.next_sequence += 1;
+                prediction.pending.push_back((sequence, direction));
+
+                connection.stream.send(
+                    &ClientMessage::Turn {
+                        sequence,
+                        direction,
+                    }
+                    .encode(),
+                );
+            }
+        }
+    }
+}
+
+/// What is shown of the server's game, kept up to date from its snapshots.
+#[derive(SystemParam)]
+pub struct ServerGame<'a> {
+    snapshots: ResMut<'a, Snapshots>,
+    game: ResMut<'a, GameState>,
+    score: ResMut<'a, Score>,
+}
+
+fn receive_snapshots(
+    time: Res<Time>,
+    mut connection: ResMut<Connection>,
+    mut server_game: ServerGame,
+    mut prediction: Option<ResMut<Prediction>>,
+    mut spectator: Option<ResMut<Spectator>>,
+    mut rng: ResMut<GameRng>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let ServerGame {
+        snapshots,
+        game,
+        score,
+    } = &mut server_game;
+
+    let tick_interval = connection.tick_interval;
+
+    if let Some(prediction) = &mut prediction {
+        if prediction.ping_timer.tick(time.delta()).just_finished() && prediction.ping.is_none() {
+            prediction.pings_sent += 1;
+
+            let id = prediction.pings_sent;
+            prediction.ping = Some((id, Instant::now()));
+            connection.stream.send(&ClientMessage::Ping(id).encode());
+        }
+    }
+
+    let stream = &mut connection.stream;
+    let mut lines = match stream.flush().and_then(|_| stream.receive()) {
+        Ok(lines) => lines,
+        Err(error) => {
+            error!("Lost the connection to the server: {}", error);
+            exit.send(AppExit);
+            return;
+        }
+    };
+
+    if !connection.backlog.is_empty() {
+        let mut backlog = std::mem::take(&mut connection.backlog);
+        backlog.append(&mut lines);
+        lines = backlog;
+    }
+
+    score.elapsed += time.delta_seconds_f64();
+    snapshots.since_latest += time.delta_seconds();
+
+    let mut received = false;
+
+    for line in lines {
+        match ServerMessage::decode(&line) {
+            Ok(ServerMessage::Snapshot(snapshot)) => {
+                score.eaten = snapshot.snakes.iter().map(|snake| snake.score).collect();
+
+                if let Some(prediction) = &mut prediction {
+                    prediction.rewind(&snapshot, tick_interval, &mut rng);
+                }
+
+                snapshots.previous = snapshots.latest.replace(snapshot);
+                snapshots.since_latest = 0.0;
+                received = true;
+            }
+            Ok(ServerMessage::Pong(id)) => {
+                if let Some(prediction) = &mut prediction {
+                    if let Some((_, sent)) = prediction.ping.filter(|&(ping, _)| ping == id) {
+                        let sample = sent.elapsed().as_secs_f64();
+                        prediction.round_trip = match prediction.round_trip {
+                            round_trip if round_trip > 0.0 => round_trip * 0.75 + sample * 0.25,
+                            _ => sample,
+                        };
+                        prediction.ping = None;
+                    }
+                }
+            }
+            Ok(ServerMessage::Joined { player, name }) => {
+                info!("{} joined as player {}", name, player + 1);
+
+                if let Some(spectator) = &mut spectator {
+                    if spectator.names.len() <= player {
+                        spectator.names.resize(player + 1, None);
+                    }
+
+                    spectator.names[player] = Some(name);
+                }
+            }
+            Ok(ServerMessage::Left { player }) => {
+                info!("Player {} left", player + 1);
+
+                if let Some(spectator) = &mut spectator {
+                    if let Some(name) = spectator.names.get_mut(player) {
+                        *name = None;
+                    }
+                }
+            }
+            Ok(message) => warn!("Unexpected message from the server: {:?}", message),
+            Err(error) => warn!("Ignoring a message from the server: {}", error),
+        }
+    }
+
+    if let Some(prediction) = &mut prediction {
+        let interval = tick_interval * prediction.game.tick_percent() as f64 / 100.0;
+        prediction
+            .timer
+            .set_duration(Duration::from_secs_f64(interval));
+
+        if !received && prediction.timer.tick(time.delta()).just_finished() {
+            prediction.step(&mut rng);
+        }
+    }
+
+    let latest = match &snapshots.latest {
+        Some(latest) => latest,
+        None => return,
+    };
+
+    // Other snakes and the food are shown as the server last had them, and our own as predicted.
+    latest.apply(game);
+
+    if let Some(prediction) = &prediction {
+        if let (Some(snake), Some(predicted)) = (
+            game.snakes.get_mut(prediction.player),
+            prediction.game.snakes.get(prediction.player),
+        ) {
+            *snake = predicted.clone();
+        }
+    }
+}
+
+/// Slides the other snakes from where the previous snapshot had them to where the latest has them,
+/// since their `Position`s only change once a tick.
+fn interpolate_snakes(
+    windows: Res<Windows>,
+    connection: Res<Connection>,
+    snapshots: Res<Snapshots>,
+    game: Res<GameState>,
+    players: Query<(&Player, &SnakeSegments)>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let (previous, latest) = match (&snapshots.previous, &snapshots.latest) {
+        (Some(previous), Some(latest)) => (previous, latest),
+        _ => return,
+    };
+
+    let window = windows.get_primary().unwrap();
+    let arena = game.rules.arena;
+    let tick_interval = connection.tick_interval * game.tick_percent() as f64 / 100.0;
+    let progress = (snapshots.since_latest / tick_interval as f32).min(1.0);
+
+    for (player, segments) in players.iter() {
+        if Some(player.0) == connection.player {
+            continue;
+        }
+
+        let (from, to) = match (previous.snakes.get(player.0), latest.snakes.get(player.0)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => continue,
+        };
+
+        for (index, &entity) in segments.0.iter().enumerate() {
+            let end = match to.segments.get(index) {
+                Some(&end) => end,
+                None => continue,
+            };
+            // New segments and those wrapping around the edge jump straight to their tile.
+            let start = from
+                .segments
+                .get(index)
+                .copied()
+                .filter(|start| (start.0 - end.0).abs() + (start.1 - end.1).abs() <= 1)
+                .unwrap_or(end);
+
+            if let Ok(mut transform) = transforms.get_mut(entity) {
+                let x = start.0 as f32 + (end.0 - start.0) as f32 * progress;
+                let y = start.1 as f32 + (end.1 - start.1) as f32 * progress;
+
+                transform.translation.x = update_axis(x, window.width(), arena.0 as f32);
+                transform.translation.y = update_axis(y, window.height(), arena.1 as f32);
+            }
+        }
+    }
+}
+
+/// Tab and the arrow keys move on to the next or previous snake, the number keys pick one, and F
+/// goes back to showing the whole arena.
+fn switch_players(
+    input: Res<Input<KeyCode>>,
+    game: Res<GameState>,
+    mut spectator: ResMut<Spectator>,
+) {
+    const NUMBER_KEYS: [KeyCode; 9] = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    let count = game.snakes.len();
+
+    if count == 0 {
+        return;
+    }
+
+    let following = spectator.following;
+    let step = |offset: usize| match following {
+        Some(player) => (player + offset) % count,
+        None => 0,
+    };
+
+    if input.just_pressed(KeyCode::Tab) || input.just_pressed(KeyCode::Right) {
+        spectator.following = Some(step(1));
+    } else if input.just_pressed(KeyCode::Left) {
+        spectator.following = Some(step(count - 1));
+    } else if input.just_pressed(KeyCode::F) {
+        spectator.following = None;
+    }
+
+    for (player, &key) in NUMBER_KEYS.iter().enumerate().take(count) {
+        if input.just_pressed(key) {
+            spectator.following = Some(player);
+        }
+    }
+}
+
+/// Zooms in on the followed snake's head, easing the camera along behind it.
+fn follow_snake(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    game: Res<GameState>,
+    spectator: Res<Spectator>,
+    mut cameras: Query<&mut Transform, With<GameCamera>>,
+) {
+    let window = windows.get_primary().unwrap();
+    let arena = game.rules.arena;
+    let head = spectator
+        .following
+        .and_then(|player| game.snakes.get(player))
+        .and_then(|snake| snake.segments.first());
+    let (target, zoom) = match head {
+        Some(head) => (
+            Vec2::new(
+                update_axis(head.0 as f32, window.width(), arena.0 as f32),
+                update_axis(head.1 as f32, window.height(), arena.1 as f32),
+            ),
+            0.5,
+        ),
+        None => (Vec2::ZERO, 1.0),
+    };
+    let blend = (time.delta_seconds() * 8.0).min(1.0);
+
+    for mut transform in cameras.iter_mut() {
+        let position = transform.translation.truncate().lerp(target, blend);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        transform.scale = transform.scale.lerp(Vec3::new(zoom, zoom, 1.0), blend);
+    }
+}
+
+/// Takes the place of `HeadlessSnakePlugin` when playing on a server, which does the simulating.
+pub(crate) struct ClientPlugin;
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let connection = app.world().get_resource::<Connection>().unwrap();
+        let watching = connection.player.is_none();
+        let rules = connection.rules.clone();
+        let config = SnakeConfig {
+            tick_interval: connection.tick_interval,
+            ..app
+                .world()
+                .get_resource::<SnakeConfig>()
+                .cloned()
+                .unwrap_or_default()
+        };
+
+        app.init_resource::<GameRng>()
+            .init_resource::<Score>()
+            .init_resource::<Snapshots>()
+            .insert_resource(config)
+            .insert_resource(rules)
+            .add_startup_system(setup_client.system())
+            // Labelled like the end of a local tick, so everything drawing the game runs after the
+            // newest snapshot is applied.
+            .add_system(receive_snapshots.system().label(SnakeAction::GameOver))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate_snakes.system().after(SnakeAction::Transform),
+            );
+
+        if watching {
+            app.add_system(switch_players.system())
+                .add_system(follow_snake.system().after(SnakeAction::GameOver));
+        } else {
+            app.add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(send_turns.system().before(SnakeAction::GameOver)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewinding_replays_the_turns_the_server_has_not_seen() {
+        let rules = GameRules::default();
+        let mut server = GameState::new(rules.clone(), &mut GameRng::new(0));
+
+        // We turned up and then left, and guessed both ahead of the server. It has only dealt with
+        // the first turn so far.
+        let mut prediction = Prediction {
+            player: 0,
+            pending: VecDeque::from(vec![(1, SnakeState::Up), (2, SnakeState::Left)]),
+            next_sequence: 3,
+            queue: InputQueue::default(),
+            game: server.clone(),
+            timer: Timer::from_seconds(0.1, true),
+            round_trip: 0.3,
+            ping_timer: Timer::from_seconds(1.0, true),
+            pings_sent: 0,
+            ping: None,
+        };
+        for direction in [SnakeState::Up, SnakeState::Left, SnakeState::Left] {
+            prediction.game.step(&[direction], &mut GameRng::new(0));
+        }
+
+        server.step(&[SnakeState::Up], &mut GameRng::new(0));
+        let snapshot = Snapshot::new(1, 1, &server);
+
+        prediction.rewind(&snapshot, 0.1, &mut GameRng::new(0));
+
+        assert_eq!(
+            prediction.pending.iter().copied().collect::<Vec<_>>(),
+            vec![(2, SnakeState::Left)]
+        );
+
+        // The snapshot is a round trip of 3 ticks behind, which are played with the left turn.
+        let mut expected = server;
+        let mut rng = GameRng::new(0);
+        for _ in 0..3 {
+            expected.step(&[SnakeState::Left], &mut rng);
+        }
+        assert_eq!(prediction.game.snakes, expected.snakes);
+        assert_eq!(prediction.game.snakes[0].heading, SnakeState::Left);
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::BotKind,
+    game::{
+        Arena, BoundaryMode, CurveShape, GameRules, GameState, SnakeState, SpeedCurve, SpeedMeasure,
+    },
+    level::Level,
+    names::parse_name,
+    rng::GameRng,
+    theme::{Theme, ThemeWatcher},
+};
+
+/// Presets for how fast a game starts and how quickly it speeds up.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Insane,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Insane => "Insane",
+        }
+    }
+
+    pub fn speed_curve(self) -> SpeedCurve {
+        match self {
+            Difficulty::Easy => SpeedCurve {
+                shape: CurveShape::Linear,
+                measure: SpeedMeasure::Score,
+                start: 130,
+                rate: 1,
+                step: 1,
+                floor: 80,
+            },
+            Difficulty::Normal => SpeedCurve {
+                shape: CurveShape::Stepped,
+                measure: SpeedMeasure::Score,
+                start: 100,
+                rate: 10,
+                step: 5,
+                floor: 50,
+            },
+            Difficulty::Hard => SpeedCurve {
+                shape: CurveShape::Exponential,
+                measure: SpeedMeasure::Score,
+                start: 80,
+                rate: 3,
+                step: 1,
+                floor: 35,
+            },
+            Difficulty::Insane => SpeedCurve {
+                shape: CurveShape::Exponential,
+                measure: SpeedMeasure::Length,
+                start: 60,
+                rate: 5,
+                step: 1,
+                floor: 25,
+            },
+        }
+    }
+}
+
+/// Settings read by the plugins when they are built. Insert it before adding `SnakeActionPlugin` or
+/// `HeadlessSnakePlugin`, otherwise the defaults below are used.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnakeConfig {
+    /// The name high scores are saved under.
+    pub player_name: String,
+    pub arena_width: u32,
+    pub arena_height: u32,
+    /// A level map to play on, which replaces the arena size above, see `Level`.
+    pub level: Option<PathBuf>,
+    pub boundary: BoundaryMode,
+    /// The number of snakes, one per player sharing the keyboard.
+    pub players: u32,
+    /// How many of the players, counting from the last, are played by the computer.
+    pub bots: u32,
+    pub bot: BotKind,
+    /// Seconds between two moves of the snake, before the speed curve is applied.
+    pub tick_interval: f64,
+    pub difficulty: Difficulty,
+    /// Replaces the speed curve of the difficulty, until another difficulty is picked in the menu.
+    pub speed_curve: Option<SpeedCurve>,
+    pub initial_length: u32,
+    pub starting_direction: SnakeState,
+    /// How likely each kind of food is to spawn, relative to the others, unless the level says
+    /// otherwise. Keyed by names like `speed_up`, and kinds left out never spawn.
+    pub food_weights: BTreeMap<String, u32>,
+    /// A built-in theme, one of `Theme::built_in_names`, or the path of a theme file, which the game
+    /// reloads whenever it changes.
+    pub theme: String,
+    pub window_width: f32,
+    pub window_height: f32,
+}
+
+impl Default for SnakeConfig {
+    fn default() -> Self {
+        SnakeConfig {
+            player_name: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .unwrap_or_else(|_| String::from("Player")),
+            arena_width: 15,
+            arena_height: 15,
+            level: None,
+            boundary: BoundaryMode::Walls,
+            players: 1,
+            bots: 0,
+            bot: BotKind::Greedy,
+            tick_interval: 0.15,
+            difficulty: Difficulty::Normal,
+            speed_curve: None,
+            initial_length: 2,
+            starting_direction: SnakeState::Right,
+            food_weights: [
+                ("normal", 20),
+                ("bonus", 3),
+                ("feast", 2),
+                ("shrink", 2),
+                ("speed_up", 1),
+                ("slow_down", 1),
+                ("ghost", 1),
+            ]
+            .iter()
+            .map(|&(name, weight)| (name.to_owned(), weight))
+            .collect(),
+            theme: String::from("classic"),
+            window_width: 1000.0,
+            window_height: 1000.0,
+        }
+    }
+}
+
+fn invalid(error: impl ToString) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+impl SnakeConfig {
+    /// Reads a TOML file, where every missing key keeps its default.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        toml::from_str(&fs::read_to_string(path)?).map_err(invalid)
+    }
+
+    /// Overrides a single key, using the same names and value syntax as the config file. Bare
+    /// words are accepted as strings, so `starting_direction=up` works without quotes.
+    pub fn set(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let value = match toml::from_str::<toml::Value>(&format!("value = {}", value)) {
+            Ok(document) => document["value"].clone(),
+            Err(_) => toml::Value::String(value.to_owned()),
+        };
+
+        let mut table = toml::Value::try_from(&*self).map_err(invalid)?;
+        table.as_table_mut().unwrap().insert(key.to_owned(), value);
+
+        *self = table.try_into().map_err(invalid)?;
+        Ok(())
+    }
+
+    /// The theme named by this config, loading it from its file if it is not a built-in one.
+    pub fn theme(&self) -> io::Result<Theme> {
+        match Theme::built_in(&self.theme) {
+            Some(theme) => Ok(theme),
+            None => Theme::load(Path::new(&self.theme)),
+        }
+    }
+
+    /// Watches the theme file for changes, unless the theme is a built-in one.
+    pub fn theme_watcher(&self) -> Option<ThemeWatcher> {
+        match Theme::built_in(&self.theme) {
+            Some(_) => None,
+            None => Some(ThemeWatcher::new(PathBuf::from(&self.theme))),
+        }
+    }
+
+    /// The rules described by this config, loading the level if there is one. Fails if any player
+    /// would start the game blocked by a wall or another snake.
+    pub fn rules(&self) -> io::Result<GameRules> {
+        let mut rules = GameRules {
+            arena: Arena(self.arena_width, self.arena_height),
+            boundary: self.boundary,
+            players: self.players,
+            initial_length: self.initial_length,
+            starting_direction: self.starting_direction,
+            speed: self
+                .speed_curve
+                .unwrap_or_else(|| self.difficulty.speed_curve()),
+            food_weights: self
+                .food_weights
+                .iter()
+                .map(|(name, &weight)| Ok((parse_name("food", name)?, weight)))
+                .collect::<io::Result<_>>()?,
+            ..Default::default()
+        };
+
+        if let Some(path) = &self.level {
+            Level::load(path)?.apply(&mut rules)?;
+        }
+
+        // Snakes that cannot be placed start the game dead, which is better caught here.
+        let game = GameState::new(rules.clone(), &mut GameRng::new(0));
+
+        if let Some(player) = game.snakes.iter().position(|snake| !snake.is_alive()) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("there is no room for player {} to start", player + 1),
+            ));
+        }
+
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::FoodKind;
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let config =
+            toml::from_str::<SnakeConfig>("arena_width = 20\nboundary = \"wrap\"").unwrap();
+
+        assert_eq!(
+            config,
+            SnakeConfig {
+                arena_width: 20,
+                boundary: BoundaryMode::Wrap,
+                ..Default::default()
+            }
+        );
+        assert!(toml::from_str::<SnakeConfig>("arena_widht = 20").is_err());
+    }
+
+    #[test]
+    fn keys_are_set_like_in_the_config_file() {
+        let mut config = SnakeConfig::default();
+
+        config.set("arena_height", "30").unwrap();
+        config.set("tick_interval", "0.1").unwrap();
+        config.set("starting_direction", "up").unwrap();
+        config.set("player_name", "\"Ada L\"").unwrap();
+        config.set("food_weights", "{ normal = 1 }").unwrap();
+
+        assert_eq!(config.arena_height, 30);
+        assert_eq!(config.tick_interval, 0.1);
+        assert_eq!(config.starting_direction, SnakeState::Up);
+        assert_eq!(config.player_name, "Ada L");
+        assert_eq!(config.food_weights.len(), 1);
+    }
+
+    #[test]
+    fn bad_keys_and_values_leave_the_config_alone() {
+        let mut config = SnakeConfig::default();
+
+        assert!(config.set("arena_size", "30").is_err());
+        assert!(config.set("arena_height", "tall").is_err());
+        assert!(config.set("starting_direction", "sideways").is_err());
+        assert_eq!(config, SnakeConfig::default());
+    }
+
+    #[test]
+    fn rules_follow_the_config() {
+        let config = SnakeConfig {
+            arena_width: 20,
+            arena_height: 10,
+            players: 2,
+            difficulty: Difficulty::Hard,
+            food_weights: [(String::from("ghost"), 4)].iter().cloned().collect(),
+            ..Default::default()
+        };
+
+        let rules = config.rules().unwrap();
+        assert_eq!(rules.arena, Arena(20, 10));
+        assert_eq!(rules.players, 2);
+        assert_eq!(rules.speed, Difficulty::Hard.speed_curve());
+        assert_eq!(rules.food_weights, vec![(FoodKind::Ghost, 4)]);
+
+        let curve = SpeedCurve {
+            floor: 10,
+            ..Difficulty::Easy.speed_curve()
+        };
+        let rules = SnakeConfig {
+            speed_curve: Some(curve),
+            ..config
+        }
+        .rules()
+        .unwrap();
+        assert_eq!(rules.speed, curve);
+    }
+
+    #[test]
+    fn rules_are_refused_without_room_for_every_player() {
+        let unknown_food = SnakeConfig {
+            food_weights: [(String::from("cake"), 1)].iter().cloned().collect(),
+            ..Default::default()
+        };
+        assert!(unknown_food.rules().is_err());
+
+        let crowded = SnakeConfig {
+            arena_height: 1,
+            players: 2,
+            ..Default::default()
+        };
+        let error = crowded.rules().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "there is no room for player 2 to start");
+    }
+}
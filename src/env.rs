@@ -0,0 +1,483 @@
+use crate::{
+    ai::{BotKind, SnakeController},
+    game::{BoundaryMode, GameRules, GameState, Position, SnakeState, StepOutcome},
+    rng::GameRng,
+};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ObservationKind {
+    /// A `[4, height, width]` grid with one channel each for the head, the bodies of every living
+    /// snake, the food and the walls. Rows are ordered by `y`, so the first row is the bottom one.
+    Grid,
+    /// An `[8, 3]` grid of rays cast from the head, starting straight ahead and going clockwise.
+    /// Each ray holds the inverse distance to the first wall, the first body and the food, or 0 if
+    /// it doesn't hit one.
+    Rays,
+}
+
+/// A flattened tensor, as expected by most learning libraries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+/// The reward given for each thing that can happen during a step, all added together.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RewardShaping {
+    /// Given for each point scored by eating, so bonus food is worth more.
+    pub food: f32,
+    pub death: f32,
+    /// Given on every step, usually a small penalty to hurry the agent along.
+    pub step: f32,
+    /// Given for each tile the head gets closer to the food, and taken away for each tile further.
+    pub approach: f32,
+}
+
+impl Default for RewardShaping {
+    fn default() -> Self {
+        RewardShaping {
+            food: 1.0,
+            death: -1.0,
+            step: 0.0,
+            approach: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvConfig {
+    /// The rules of every episode. Any players after the first are played by `opponent` bots.
+    pub rules: GameRules,
+    pub observation: ObservationKind,
+    pub rewards: RewardShaping,
+    /// Ends an episode after this many steps, even if the snake is still alive.
+    pub max_steps: Option<u64>,
+    pub opponent: BotKind,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            rules: GameRules::default(),
+            observation: ObservationKind::Grid,
+            rewards: RewardShaping::default(),
+            max_steps: None,
+            opponent: BotKind::Greedy,
+        }
+    }
+}
+
+impl EnvConfig {
+    pub fn observation_shape(&self) -> Vec<usize> {
+        let arena = self.rules.arena;
+
+        match self.observation {
+            ObservationKind::Grid => vec![4, arena.1 as usize, arena.0 as usize],
+            ObservationKind::Rays => vec![8, 3],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StepInfo {
+    pub outcome: StepOutcome,
+    pub score: u32,
+    pub length: usize,
+    pub steps: u64,
+    /// Whether the episode ended because of `EnvConfig::max_steps` rather than the snake dying.
+    pub truncated: bool,
+}
+
+/// A reinforcement learning environment in the style of OpenAI Gym, where the agent controls the
+/// first snake. It steps the same `GameState` as the game itself, so agents learn the real rules.
+pub struct SnakeEnv {
+    config: EnvConfig,
+    rng: GameRng,
+    game: GameState,
+    opponents: Vec<Box<dyn SnakeController>>,
+    steps: u64,
+}
+
+impl SnakeEnv {
+    pub fn new(config: EnvConfig) -> Self {
+        let mut rng = GameRng::default();
+        let game = GameState::new(config.rules.clone(), &mut rng);
+
+        let mut env = SnakeEnv {
+            config,
+            rng,
+            game,
+            opponents: Vec::new(),
+            steps: 0,
+        };
+
+        env.start_episode(env.rng.seed());
+        env
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    /// Starts a new episode, which plays out the same way for the same seed and actions.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.start_episode(seed);
+        self.observe()
+    }
+
+    /// Like `reset`, but without building an observation, for callers using `observe_into`.
+    pub fn start_episode(&mut self, seed: u64) {
+        self.rng = GameRng::new(seed);
+        self.game.reset(&mut self.rng);
+        self.opponents = (1..self.game.snakes.len())
+            .map(|_| self.config.opponent.controller(&self.config.rules))
+            .collect();
+        self.steps = 0;
+    }
+
+    /// Moves the snake one tile, after turning it towards `action` unless that would reverse it.
+    pub fn step(&mut self, action: SnakeState) -> (Observation, f32, bool, StepInfo) {
+        let (reward, done, info) = self.advance(action);
+
+        (self.observe(), reward, done, info)
+    }
+
+    /// Like `step`, but without building an observation, for callers using `observe_into`.
+    pub fn advance(&mut self, action: SnakeState) -> (f32, bool, StepInfo) {
+        let rewards = self.config.rewards;
+        let distance_before = self.food_distance();
+
+        let mut actions = vec![action];
+        for (index, opponent) in self.opponents.iter_mut().enumerate() {
+            let snake = &self.game.snakes[index + 1];
+
+            actions.push(match snake.is_alive() {
+                true => opponent.next_direction(&self.game, index + 1),
+                false => snake.heading,
+            });
+        }
+
+        let outcome = self.game.step(&actions, &mut self.rng)[0];
+        self.steps += 1;
+
+        let mut reward = match outcome {
+            StepOutcome::Moved => rewards.step,
+            StepOutcome::Ate(kind) => rewards.step + rewards.food * kind.effect().points as f32,
+            StepOutcome::Died(_) => rewards.step + rewards.death,
+            StepOutcome::Dead => 0.0,
+        };
+
+        if outcome == StepOutcome::Moved {
+            if let (Some(before), Some(after)) = (distance_before, self.food_distance()) {
+                reward += rewards.approach * (before - after) as f32;
+            }
+        }
+
+        let snake = &self.game.snakes[0];
+        // The board can fill up completely, leaving nowhere for the food to go.
+        let finished = !snake.is_alive() || self.game.food.is_none();
+        let truncated = !finished && self.config.max_steps.is_some_and(|max| self.steps >= max);
+        let info = StepInfo {
+            outcome,
+            score: snake.score,
+            length: snake.segments.len(),
+            steps: self.steps,
+            truncated,
+        };
+
+        (reward, finished || truncated, info)
+    }
+
+    pub fn observe(&self) -> Observation {
+        let shape = self.observation_shape();
+        let mut data = vec![0.0; shape.iter().product()];
+
+        self.observe_into(&mut data);
+        Observation { shape, data }
+    }
+
+    pub fn observation_shape(&self) -> Vec<usize> {
+        self.config.observation_shape()
+    }
+
+    /// Writes the observation into a buffer of the size given by `observation_shape`, without
+    /// allocating.
+    pub fn observe_into(&self, data: &mut [f32]) {
+        match self.config.observation {
+            ObservationKind::Grid => self.grid(data),
+            ObservationKind::Rays => self.rays(data),
+        }
+    }
+
+    /// The Manhattan distance from the head to the food, taking the shorter way around when the
+    /// arena wraps.
+    fn food_distance(&self) -> Option<i32> {
+        let food = self.game.food?;
+        let offset = food.position - *self.game.snakes[0].segments.first()?;
+        let arena = self.game.rules.arena;
+
+        let axis = |offset: i32, length: u32| match self.game.rules.boundary {
+            BoundaryMode::Walls => offset.abs(),
+            BoundaryMode::Wrap => offset.abs().min(length as i32 - offset.abs()),
+        };
+
+        Some(axis(offset.0, arena.0) + axis(offset.1, arena.1))
+    }
+
+    fn grid(&self, data: &mut [f32]) {
+        let arena = self.game.rules.arena;
+        let (width, height) = (arena.0 as usize, arena.1 as usize);
+
+        data.fill(0.0);
+
+        let mut set = |channel: usize, position: Position| {
+            if self.game.in_bounds(position) {
+                data[(channel * height + position.1 as usize) * width + position.0 as usize] = 1.0;
+            }
+        };
+
+        for (player, snake) in self.game.snakes.iter().enumerate() {
+            if !snake.is_alive() {
+                continue;
+            }
+
+            for (index, &segment) in snake.segments.iter().enumerate() {
+                set(if player == 0 && index == 0 { 0 } else { 1 }, segment);
+            }
+        }
+
+        if let Some(food) = self.game.food {
+            set(2, food.position);
+        }
+
+        for &wall in &self.game.rules.walls {
+            set(3, wall);
+        }
+    }
+
+    fn rays(&self, data: &mut [f32]) {
+        let snake = &self.game.snakes[0];
+
+        // A snake that had no room to start has no head to cast rays from.
+        let head = match snake.segments.first() {
+            Some(&head) => head,
+            None => return data.fill(0.0),
+        };
+
+        let arena = self.game.rules.arena;
+        let forward = snake.heading.offset();
+        let right = Position(forward.1, -forward.0);
+        let back = Position(-forward.0, -forward.1);
+        let left = Position(-right.0, -right.1);
+        let directions = [
+            forward,
+            forward + right,
+            right,
+            back + right,
+            back,
+            back + left,
+            left,
+            forward + left,
+        ];
+
+        for (direction, ray) in directions.into_iter().zip(data.chunks_mut(3)) {
+            let (mut wall, mut body, mut food) = (0.0, 0.0, 0.0);
+            let mut position = head;
+
+            // A ray that wraps around without hitting anything gives up after crossing the arena.
+            for distance in 1..=arena.0.max(arena.1) {
+                position = position + direction;
+
+                if self.game.rules.boundary == BoundaryMode::Wrap {
+                    position = self.game.wrap(position);
+                }
+
+                let inverse = 1.0 / distance as f32;
+
+                if !self.game.in_bounds(position) || self.game.is_wall(position) {
+                    wall = inverse;
+                    break;
+                }
+
+                if body == 0.0
+                    && self
+                        .game
+                        .snakes
+                        .iter()
+                        .any(|snake| snake.is_alive() && snake.segments.contains(&position))
+                {
+                    body = inverse;
+                }
+
+                if food == 0.0 && self.game.food.map(|food| food.position) == Some(position) {
+                    food = inverse;
+                }
+            }
+
+            ray.copy_from_slice(&[wall, body, food]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Arena, DeathCause, Food, FoodKind};
+
+    /// An environment on an 8 by 6 arena with a wall in the bottom left corner, whose snake starts
+    /// at (4, 3) heading right with its tail at (3, 3).
+    fn env(observation: ObservationKind, rewards: RewardShaping) -> SnakeEnv {
+        let mut env = SnakeEnv::new(EnvConfig {
+            rules: GameRules {
+                arena: Arena(8, 6),
+                walls: vec![Position(0, 0)],
+                spawns: vec![Position(4, 3)],
+                ..Default::default()
+            },
+            observation,
+            rewards,
+            ..Default::default()
+        });
+
+        env.reset(0);
+        env
+    }
+
+    fn food_at(x: i32, y: i32) -> Option<Food> {
+        Some(Food {
+            position: Position(x, y),
+            kind: FoodKind::Normal,
+            ticks_left: None,
+        })
+    }
+
+    #[test]
+    fn grids_have_a_channel_each_for_the_head_bodies_food_and_walls() {
+        let mut env = env(ObservationKind::Grid, RewardShaping::default());
+        env.game.food = food_at(6, 1);
+
+        let observation = env.observe();
+        assert_eq!(observation.shape, vec![4, 6, 8]);
+        assert_eq!(observation.data.len(), 4 * 6 * 8);
+
+        let channel = |index: usize| &observation.data[index * 48..(index + 1) * 48];
+        let marked = |index: usize| {
+            channel(index)
+                .iter()
+                .enumerate()
+                .filter(|&(_, &value)| value == 1.0)
+                .map(|(tile, _)| Position((tile % 8) as i32, (tile / 8) as i32))
+                .collect::<Vec<Position>>()
+        };
+
+        assert_eq!(marked(0), vec![Position(4, 3)]);
+        assert_eq!(marked(1), vec![Position(3, 3)]);
+        assert_eq!(marked(2), vec![Position(6, 1)]);
+        assert_eq!(marked(3), vec![Position(0, 0)]);
+    }
+
+    #[test]
+    fn rays_hold_the_inverse_distance_to_what_they_hit() {
+        let mut env = env(ObservationKind::Rays, RewardShaping::default());
+        env.game.food = food_at(6, 3);
+
+        let observation = env.observe();
+        assert_eq!(observation.shape, vec![8, 3]);
+        assert_eq!(observation.data.len(), 24);
+
+        // Straight ahead, the food is 2 tiles away and the edge of the arena 4.
+        assert_eq!(&observation.data[0..3], &[0.25, 0.0, 0.5]);
+        // Straight behind, the tail is right there and the edge is 5 tiles away.
+        assert_eq!(&observation.data[12..15], &[0.2, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn rewards_follow_the_shaping() {
+        let rewards = RewardShaping {
+            food: 2.0,
+            death: -5.0,
+            step: -0.25,
+            approach: 0.5,
+        };
+        let mut env = env(ObservationKind::Grid, rewards);
+        env.game.food = food_at(6, 3);
+
+        // Getting closer to the food.
+        let (_, reward, done, _) = env.step(SnakeState::Right);
+        assert_eq!(reward, -0.25 + 0.5);
+        assert!(!done);
+
+        let (_, reward, _, info) = env.step(SnakeState::Right);
+        assert_eq!(info.outcome, StepOutcome::Ate(FoodKind::Normal));
+        assert_eq!(reward, -0.25 + 2.0);
+
+        // Getting further from the food.
+        env.game.food = food_at(0, 3);
+        let (_, reward, _, _) = env.step(SnakeState::Up);
+        assert_eq!(reward, -0.25 - 0.5);
+
+        env.step(SnakeState::Up);
+        let (_, reward, done, info) = env.step(SnakeState::Up);
+        assert_eq!(info.outcome, StepOutcome::Died(DeathCause::Wall));
+        assert_eq!(reward, -0.25 - 5.0);
+        assert!(done);
+        assert!(!info.truncated);
+    }
+
+    #[test]
+    fn episodes_are_truncated_after_max_steps() {
+        let mut env = SnakeEnv::new(EnvConfig {
+            max_steps: Some(3),
+            ..Default::default()
+        });
+        env.reset(0);
+
+        for step in 1..=3 {
+            let (_, _, done, info) = env.step(SnakeState::Up);
+            assert_eq!(info.steps, step);
+            assert_eq!(done, step == 3);
+            assert_eq!(info.truncated, step == 3);
+        }
+
+        env.reset(0);
+        let (_, _, done, info) = env.step(SnakeState::Up);
+        assert_eq!(info.steps, 1);
+        assert!(!done);
+    }
+
+    #[test]
+    fn a_snake_with_no_room_to_start_sees_nothing() {
+        for kind in [ObservationKind::Grid, ObservationKind::Rays] {
+            let mut env = SnakeEnv::new(EnvConfig {
+                rules: GameRules {
+                    spawns: vec![Position(3, 3)],
+                    walls: vec![Position(3, 3)],
+                    ..Default::default()
+                },
+                observation: kind,
+                ..Default::default()
+            });
+
+            // Neither the head channel of the grid nor any of the rays has anything in it.
+            let shape = env.observation_shape();
+            let head_values = match kind {
+                ObservationKind::Grid => shape[1] * shape[2],
+                ObservationKind::Rays => shape.iter().product(),
+            };
+            let observation = env.reset(0);
+            assert!(observation.data[..head_values]
+                .iter()
+                .all(|&value| value == 0.0));
+
+            let (_, reward, done, info) = env.step(SnakeState::Up);
+            assert_eq!(reward, 0.0);
+            assert!(done);
+            assert_eq!(info.outcome, StepOutcome::Dead);
+        }
+    }
+}
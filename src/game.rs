@@ -0,0 +1,1106 @@
+use std::{
+    collections::VecDeque,
+    ops::{Add, Sub},
+};
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Arena(pub u32, pub u32);
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena(15, 15)
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnakeState {
+    Left,
+    Right,
+    Down,
+    Up,
+}
+
+impl SnakeState {
+    pub const ALL: [SnakeState; 4] = [
+        SnakeState::Left,
+        SnakeState::Right,
+        SnakeState::Down,
+        SnakeState::Up,
+    ];
+
+    pub fn opposite(self) -> SnakeState {
+        match self {
+            SnakeState::Left => SnakeState::Right,
+            SnakeState::Right => SnakeState::Left,
+            SnakeState::Down => SnakeState::Up,
+            SnakeState::Up => SnakeState::Down,
+        }
+    }
+
+    /// The change in position from moving one tile in this direction.
+    pub fn offset(self) -> Position {
+        match self {
+            SnakeState::Left => Position(-1, 0),
+            SnakeState::Right => Position(1, 0),
+            SnakeState::Down => Position(0, -1),
+            SnakeState::Up => Position(0, 1),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct Position(pub i32, pub i32);
+
+impl Add for Position {
+    type Output = Position;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Position(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl Sub for Position {
+    type Output = Position;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Position(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+/// What happens when the snake moves past the edge of the arena.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryMode {
+    Walls,
+    Wrap,
+}
+
+/// The shape of a `SpeedCurve`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CurveShape {
+    /// Keeps the starting speed for the whole game.
+    Constant,
+    /// Takes `rate` percent off the tick interval for every `step` of progress, pro rata.
+    Linear,
+    /// Takes `rate` percent off the tick interval each time another `step` of progress is made.
+    Stepped,
+    /// Shortens the tick interval by `rate` percent of what it was, for every `step` of progress.
+    Exponential,
+}
+
+/// What a `SpeedCurve` counts as progress, taken from whichever snake is furthest ahead.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedMeasure {
+    Score,
+    /// Segments grown beyond the initial length.
+    Length,
+}
+
+/// How the tick interval changes as the game goes on, as a percentage of the configured one.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SpeedCurve {
+    pub shape: CurveShape,
+    pub measure: SpeedMeasure,
+    /// The percentage to start the game at.
+    pub start: u32,
+    pub rate: u32,
+    pub step: u32,
+    /// The percentage the curve never goes below, however far the game gets.
+    pub floor: u32,
+}
+
+impl Default for SpeedCurve {
+    fn default() -> Self {
+        SpeedCurve {
+            shape: CurveShape::Constant,
+            measure: SpeedMeasure::Score,
+            start: 100,
+            rate: 0,
+            step: 1,
+            floor: 0,
+        }
+    }
+}
+
+impl SpeedCurve {
+    /// The tick interval after the given progress, as a percentage of the configured one.
+    pub fn percent(&self, progress: u32) -> u32 {
+        let step = self.step.max(1);
+        let reduction = match self.shape {
+            CurveShape::Constant => 0,
+            CurveShape::Linear => self.rate.saturating_mul(progress) / step,
+            CurveShape::Stepped => self.rate.saturating_mul(progress / step),
+            CurveShape::Exponential => {
+                let remaining =
+                    (1.0 - self.rate.min(100) as f64 / 100.0).powf(progress as f64 / step as f64);
+                100 - (remaining * 100.0).round() as u32
+            }
+        };
+
+        (self.start * (100 - reduction.min(100)) / 100).max(self.floor.min(self.start))
+    }
+}
+
+/// The kinds of food, each with its own `FoodEffect`.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FoodKind {
+    Normal,
+    Bonus,
+    Feast,
+    Shrink,
+    SpeedUp,
+    SlowDown,
+    Ghost,
+}
+
+impl FoodKind {
+    pub const ALL: [FoodKind; 7] = [
+        FoodKind::Normal,
+        FoodKind::Bonus,
+        FoodKind::Feast,
+        FoodKind::Shrink,
+        FoodKind::SpeedUp,
+        FoodKind::SlowDown,
+        FoodKind::Ghost,
+    ];
+
+    pub fn effect(self) -> FoodEffect {
+        let normal = FoodEffect {
+            points: 1,
+            growth: 1,
+            tick_percent: 100,
+            ghost: false,
+            duration: 0,
+            lifetime: 0,
+        };
+
+        match self {
+            FoodKind::Normal => normal,
+            FoodKind::Bonus => FoodEffect {
+                points: 5,
+                lifetime: 40,
+                ..normal
+            },
+            FoodKind::Feast => FoodEffect {
+                points: 2,
+                growth: 4,
+                lifetime: 60,
+                ..normal
+            },
+            FoodKind::Shrink => FoodEffect {
+                growth: -3,
+                lifetime: 60,
+                ..normal
+            },
+            FoodKind::SpeedUp => FoodEffect {
+                tick_percent: 60,
+                duration: 30,
+                lifetime: 60,
+                ..normal
+            },
+            FoodKind::SlowDown => FoodEffect {
+                tick_percent: 160,
+                duration: 30,
+                lifetime: 60,
+                ..normal
+            },
+            FoodKind::Ghost => FoodEffect {
+                ghost: true,
+                duration: 40,
+                lifetime: 60,
+                ..normal
+            },
+        }
+    }
+}
+
+/// What eating a kind of food does, and how long it stays around uneaten.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct FoodEffect {
+    pub points: u32,
+    /// Segments the snake grows by over the next ticks, or loses from its tail at once when
+    /// negative, though never its head.
+    pub growth: i32,
+    /// The tick interval while the effect lasts, as a percentage of the usual one. As every snake
+    /// moves on the same tick, this speeds up or slows down the whole game.
+    pub tick_percent: u32,
+    /// Lets the snake pass through its own body while the effect lasts.
+    pub ghost: bool,
+    /// Ticks that speed changes and ghost mode last.
+    pub duration: u32,
+    /// Ticks before the food disappears if nobody eats it, or 0 for it to stay until eaten.
+    pub lifetime: u32,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Food {
+    pub position: Position,
+    pub kind: FoodKind,
+    /// Ticks until the food disappears, for kinds with a lifetime.
+    pub ticks_left: Option<u32>,
+}
+
+/// Everything that decides how a game starts and plays out.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct GameRules {
+    pub arena: Arena,
+    pub boundary: BoundaryMode,
+    /// Tiles that block the snake, such as those of a `Level`.
+    pub walls: Vec<Position>,
+    /// Where each player's head starts. Players without one are spread out along the middle column.
+    pub spawns: Vec<Position>,
+    pub players: u32,
+    pub initial_length: u32,
+    pub starting_direction: SnakeState,
+    /// How likely each kind of food is to be the next one spawned, relative to the others.
+    pub food_weights: Vec<(FoodKind, u32)>,
+    pub speed: SpeedCurve,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            arena: Arena::default(),
+            boundary: BoundaryMode::Walls,
+            walls: Vec::new(),
+            spawns: Vec::new(),
+            players: 1,
+            initial_length: 2,
+            starting_direction: SnakeState::Right,
+            food_weights: vec![(FoodKind::Normal, 1)],
+            speed: SpeedCurve::default(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeathCause {
+    Wall,
+    OwnBody,
+    OtherSnake,
+    /// Two snakes moved onto the same tile, killing both.
+    HeadOn,
+    /// The player left the game, taking their snake with them.
+    Left,
+}
+
+/// What happened to a single snake during a tick.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StepOutcome {
+    Moved,
+    Ate(FoodKind),
+    Died(DeathCause),
+    /// The snake had already died before this tick.
+    Dead,
+}
+
+/// Turns waiting to be applied, one per tick. Each is checked against the turn queued before it
+/// rather than the current heading, so pressing Up then Left within a single tick makes both turns.
+#[derive(Clone, Debug, Default)]
+pub struct InputQueue {
+    pending: VecDeque<SnakeState>,
+}
+
+impl InputQueue {
+    pub const CAPACITY: usize = 3;
+
+    /// Queues a turn, returning whether it was accepted. Turns that would not change direction or
+    /// would reverse the snake into itself are dropped, as are any once the queue is full.
+    pub fn push(&mut self, direction: SnakeState, heading: SnakeState) -> bool {
+        let previous = self.pending.back().copied().unwrap_or(heading);
+
+        if self.pending.len() >= Self::CAPACITY
+            || direction == previous
+            || direction == previous.opposite()
+        {
+            return false;
+        }
+
+        self.pending.push_back(direction);
+        true
+    }
+
+    /// The direction to feed into the next tick, keeping the current heading if nothing is queued.
+    pub fn next(&mut self, heading: SnakeState) -> SnakeState {
+        self.pending.pop_front().unwrap_or(heading)
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snake {
+    /// The body, head first.
+    pub segments: Vec<Position>,
+    pub heading: SnakeState,
+    pub score: u32,
+    /// Segments still to be added, one per tick, by keeping the tail where it is.
+    pub growth: u32,
+    /// Ticks of ghost mode left, during which the snake passes through its own body.
+    pub ghost: u32,
+    /// Set once the snake has died, after which it no longer moves or blocks other snakes.
+    pub death: Option<DeathCause>,
+}
+
+impl Snake {
+    pub fn head(&self) -> Position {
+        self.segments[0]
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.death.is_none()
+    }
+}
+
+/// The complete state of a game, independent of any renderer. Given the same inputs and the same
+/// random number generator, `step` always produces the same game.
+#[derive(Clone, Debug)]
+pub struct GameState {
+    pub rules: GameRules,
+    /// One snake per player, in player order.
+    pub snakes: Vec<Snake>,
+    pub food: Option<Food>,
+    /// A speed change from food still in effect, as its `tick_percent` and the ticks it has left.
+    pub speed: Option<(u32, u32)>,
+}
+
+impl GameState {
+    pub fn new(rules: GameRules, rng: &mut impl Rng) -> Self {
+        let mut state = GameState {
+            rules,
+            snakes: Vec::new(),
+            food: None,
+            speed: None,
+        };
+
+        state.reset(rng);
+        state
+    }
+
+    pub fn reset(&mut self, rng: &mut impl Rng) {
+        self.snakes.clear();
+        self.food = None;
+        self.speed = None;
+
+        for player in 0..self.rules.players.max(1) as usize {
+            let snake = self.spawn_snake(player).unwrap_or(Snake {
+                segments: Vec::new(),
+                heading: self.rules.starting_direction,
+                score: 0,
+                growth: 0,
+                ghost: 0,
+                death: Some(DeathCause::Wall),
+            });
+
+            self.snakes.push(snake);
+        }
+
+        self.food = self.random_food(rng);
+    }
+
+    /// A new snake at the spawn of `player`, or `None` if something is in the way. Putting it in
+    /// place of a dead snake brings a player back into a game in progress.
+    pub fn spawn_snake(&self, player: usize) -> Option<Snake> {
+        let arena = self.rules.arena;
+        let players = self.rules.players.max(1);
+        let offset = self.rules.starting_direction.offset();
+
+        // Snakes without a spawn of their own are spread out evenly along the middle column.
+        let spawn = self.rules.spawns.get(player).copied().unwrap_or(Position(
+            (arena.0 / 2) as i32,
+            (arena.1 * (2 * player as u32 + 1) / (2 * players)) as i32,
+        ));
+
+        // The body trails behind the head, cut short if it would not fit in the arena.
+        let segments = (0..self.rules.initial_length.max(1) as i32)
+            .map(|index| spawn - Position(offset.0 * index, offset.1 * index))
+            .take_while(|&position| self.is_free(position))
+            .collect::<Vec<Position>>();
+
+        if segments.is_empty() {
+            return None;
+        }
+
+        Some(Snake {
+            segments,
+            heading: self.rules.starting_direction,
+            score: 0,
+            growth: 0,
+            ghost: 0,
+            death: None,
+        })
+    }
+
+    /// Takes a snake off the board, as when its player leaves.
+    pub fn remove(&mut self, player: usize) {
+        if let Some(snake) = self.snakes.get_mut(player) {
+            snake.segments.clear();
+            snake.death = Some(DeathCause::Left);
+        }
+    }
+
+    /// How far the leading snake has got, as measured by the speed curve of the rules.
+    pub fn progress(&self) -> u32 {
+        let initial_length = self.rules.initial_length as usize;
+
+        self.snakes
+            .iter()
+            .map(|snake| match self.rules.speed.measure {
+                SpeedMeasure::Score => snake.score,
+                SpeedMeasure::Length => snake.segments.len().saturating_sub(initial_length) as u32,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The tick interval as a percentage of the configured one, following the speed curve of the
+    /// rules and changed for a while by food like `FoodKind::SpeedUp`.
+    pub fn tick_percent(&self) -> u32 {
+        let percent = self.rules.speed.percent(self.progress());
+
+        self.speed
+            .map_or(percent, |(food_percent, _)| percent * food_percent / 100)
+    }
+
+    /// Whether every snake has died.
+    pub fn is_over(&self) -> bool {
+        self.snakes.iter().all(|snake| !snake.is_alive())
+    }
+
+    pub fn in_bounds(&self, position: Position) -> bool {
+        position.0 >= 0
+            && position.1 >= 0
+            && (position.0 as u32) < self.rules.arena.0
+            && (position.1 as u32) < self.rules.arena.1
+    }
+
+    pub fn is_wall(&self, position: Position) -> bool {
+        self.rules.walls.contains(&position)
+    }
+
+    /// Whether a tile is inside the arena and not taken by a wall or a living snake.
+    pub fn is_free(&self, position: Position) -> bool {
+        self.in_bounds(position)
+            && !self.is_wall(position)
+            && !self
+                .snakes
+                .iter()
+                .any(|snake| snake.is_alive() && snake.segments.contains(&position))
+    }
+
+    /// Brings a position that left the arena back in on the opposite edge.
+    pub fn wrap(&self, position: Position) -> Position {
+        let arena = self.rules.arena;
+
+        Position(
+            position.0.rem_euclid(arena.0 as i32),
+            position.1.rem_euclid(arena.1 as i32),
+        )
+    }
+
+    /// The tile one step towards `direction`, wrapped around if the boundary allows it.
+    pub fn neighbour(&self, position: Position, direction: SnakeState) -> Position {
+        let position = position + direction.offset();
+
+        match self.rules.boundary {
+            BoundaryMode::Walls => position,
+            BoundaryMode::Wrap => self.wrap(position),
+        }
+    }
+
+    /// Where a snake's head would be after moving one tile towards `direction`.
+    pub fn next_head(&self, snake: &Snake, direction: SnakeState) -> Position {
+        self.neighbour(snake.head(), direction)
+    }
+
+    /// Advances the game by one tick, moving every living snake at once. Each snake first turns
+    /// towards its entry in `inputs` unless that would reverse it into itself, and snakes without
+    /// an entry keep their heading.
+    pub fn step(&mut self, inputs: &[SnakeState], rng: &mut impl Rng) -> Vec<StepOutcome> {
+        for (snake, &input) in self.snakes.iter_mut().zip(inputs) {
+            if snake.is_alive() && input != snake.heading.opposite() {
+                snake.heading = input;
+            }
+        }
+
+        let new_heads = self
+            .snakes
+            .iter()
+            .map(|snake| {
+                snake
+                    .is_alive()
+                    .then(|| self.next_head(snake, snake.heading))
+            })
+            .collect::<Vec<Option<Position>>>();
+
+        // Every snake is checked against the board as it was before anyone moved, so tails are
+        // still counted, as they only move out of the way once the heads have moved.
+        let deaths = self
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(index, snake)| {
+                let new_head = new_heads[index]?;
+                let others = || {
+                    self.snakes
+                        .iter()
+                        .enumerate()
+                        .filter(move |&(other, snake)| other != index && snake.is_alive())
+                };
+
+                if !self.in_bounds(new_head) || self.is_wall(new_head) {
+                    Some(DeathCause::Wall)
+                } else if others().any(|(other, _)| new_heads[other] == Some(new_head)) {
+                    Some(DeathCause::HeadOn)
+                } else if snake.ghost == 0 && snake.segments.contains(&new_head) {
+                    Some(DeathCause::OwnBody)
+                } else if others().any(|(_, other)| other.segments.contains(&new_head)) {
+                    Some(DeathCause::OtherSnake)
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<Option<DeathCause>>>();
+
+        let mut outcomes = Vec::with_capacity(self.snakes.len());
+        let mut eaten = false;
+
+        // Effects wear off before anything is eaten, so that a fresh one lasts its full duration.
+        self.speed = self
+            .speed
+            .and_then(|(percent, ticks)| (ticks > 1).then(|| (percent, ticks - 1)));
+
+        for (index, snake) in self.snakes.iter_mut().enumerate() {
+            let new_head = match (new_heads[index], deaths[index]) {
+                (None, _) => {
+                    outcomes.push(StepOutcome::Dead);
+                    continue;
+                }
+                (Some(_), Some(cause)) => {
+                    snake.death = Some(cause);
+                    outcomes.push(StepOutcome::Died(cause));
+                    continue;
+                }
+                (Some(new_head), None) => new_head,
+            };
+
+            snake.ghost = snake.ghost.saturating_sub(1);
+            snake.segments.insert(0, new_head);
+
+            let food = self.food.filter(|food| food.position == new_head);
+
+            if let Some(food) = food {
+                let effect = food.kind.effect();
+
+                let growth = snake.growth as i32 + effect.growth;
+                snake.score += effect.points;
+                snake.growth = growth.max(0) as u32;
+
+                // Shrinking past the growth still to come takes segments off the tail, though the
+                // snake never ends the tick shorter than two segments.
+                if growth < 0 {
+                    let length = snake.segments.len().saturating_sub(-growth as usize);
+                    snake.segments.truncate(length.max(3));
+                }
+
+                if effect.ghost {
+                    snake.ghost = effect.duration;
+                }
+
+                if effect.tick_percent != 100 {
+                    self.speed = Some((effect.tick_percent, effect.duration));
+                }
+
+                eaten = true;
+                outcomes.push(StepOutcome::Ate(food.kind));
+            } else {
+                outcomes.push(StepOutcome::Moved);
+            }
+
+            if snake.growth > 0 {
+                snake.growth -= 1;
+            } else {
+                snake.segments.pop();
+            }
+        }
+
+        if eaten {
+            self.food = None;
+            self.food = self.random_food(rng);
+        } else if let Some(food) = &mut self.food {
+            match food.ticks_left {
+                Some(ticks) if ticks <= 1 => {
+                    self.food = None;
+                    self.food = self.random_food(rng);
+                }
+                Some(ticks) => food.ticks_left = Some(ticks - 1),
+                None => {}
+            }
+        }
+
+        outcomes
+    }
+
+    /// New food on a free tile, of a kind picked by `GameRules::food_weights`.
+    fn random_food(&self, rng: &mut impl Rng) -> Option<Food> {
+        let position = self.random_free_position(rng)?;
+        let kinds = self
+            .rules
+            .food_weights
+            .iter()
+            .filter(|&&(_, weight)| weight > 0)
+            .collect::<Vec<&(FoodKind, u32)>>();
+
+        // A single kind takes nothing from the generator, so games with only normal food play out
+        // the same as they did before there were other kinds.
+        let kind = match kinds[..] {
+            [] => FoodKind::Normal,
+            [&(kind, _)] => kind,
+            _ => kinds
+                .choose_weighted(rng, |&&(_, weight)| weight)
+                .map_or(FoodKind::Normal, |&&(kind, _)| kind),
+        };
+        let lifetime = kind.effect().lifetime;
+
+        Some(Food {
+            position,
+            kind,
+            ticks_left: Some(lifetime).filter(|&lifetime| lifetime > 0),
+        })
+    }
+
+    fn random_free_position(&self, rng: &mut impl Rng) -> Option<Position> {
+        let arena = self.rules.arena;
+        let mut all_positions = Vec::with_capacity(arena.0 as usize * arena.1 as usize);
+
+        for x in 0..arena.0 {
+            for y in 0..arena.1 {
+                all_positions.push(Position(x as i32, y as i32))
+            }
+        }
+
+        all_positions = all_positions
+            .iter()
+            .copied()
+            .filter(|&position| {
+                self.is_free(position) && self.food.map(|food| food.position) != Some(position)
+            })
+            .collect();
+        all_positions.choose(rng).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::GameRng;
+
+    /// A game on an empty 10 by 10 arena with a snake heading right for each list of segments, head
+    /// first, and no food.
+    fn game_with(snakes: &[&[(i32, i32)]]) -> GameState {
+        GameState {
+            rules: GameRules {
+                arena: Arena(10, 10),
+                players: snakes.len() as u32,
+                ..Default::default()
+            },
+            snakes: snakes
+                .iter()
+                .map(|segments| Snake {
+                    segments: segments.iter().map(|&(x, y)| Position(x, y)).collect(),
+                    heading: SnakeState::Right,
+                    score: 0,
+                    growth: 0,
+                    ghost: 0,
+                    death: None,
+                })
+                .collect(),
+            food: None,
+            speed: None,
+        }
+    }
+
+    fn food(x: i32, y: i32, kind: FoodKind) -> Option<Food> {
+        Some(Food {
+            position: Position(x, y),
+            kind,
+            ticks_left: None,
+        })
+    }
+
+    #[test]
+    fn hitting_a_wall_kills_the_snake() {
+        let mut game = game_with(&[&[(9, 5), (8, 5)]]);
+        let outcomes = game.step(&[SnakeState::Right], &mut GameRng::new(0));
+
+        assert_eq!(outcomes, vec![StepOutcome::Died(DeathCause::Wall)]);
+        assert_eq!(game.snakes[0].death, Some(DeathCause::Wall));
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![Position(9, 5), Position(8, 5)]
+        );
+        assert!(game.is_over());
+
+        let outcomes = game.step(&[SnakeState::Up], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Dead]);
+    }
+
+    #[test]
+    fn level_walls_kill_the_snake() {
+        let mut game = game_with(&[&[(4, 5), (3, 5)]]);
+        game.rules.walls.push(Position(5, 5));
+
+        let outcomes = game.step(&[SnakeState::Right], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Died(DeathCause::Wall)]);
+    }
+
+    #[test]
+    fn running_into_its_own_body_kills_the_snake() {
+        let mut game = game_with(&[&[(5, 5), (5, 6), (6, 6), (6, 5), (6, 4)]]);
+        game.snakes[0].heading = SnakeState::Down;
+
+        let outcomes = game.step(&[SnakeState::Right], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Died(DeathCause::OwnBody)]);
+    }
+
+    #[test]
+    fn ghosts_pass_through_their_own_body() {
+        let mut game = game_with(&[&[(5, 5), (5, 6), (6, 6), (6, 5), (6, 4)]]);
+        game.snakes[0].heading = SnakeState::Down;
+        game.snakes[0].ghost = 2;
+
+        let outcomes = game.step(&[SnakeState::Right], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Moved]);
+        assert_eq!(game.snakes[0].ghost, 1);
+    }
+
+    #[test]
+    fn reversing_is_ignored() {
+        let mut game = game_with(&[&[(5, 5), (4, 5)]]);
+
+        let outcomes = game.step(&[SnakeState::Left], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Moved]);
+        assert_eq!(game.snakes[0].head(), Position(6, 5));
+    }
+
+    #[test]
+    fn quick_turns_are_played_on_the_following_ticks() {
+        let mut game = game_with(&[&[(5, 5), (4, 5)]]);
+        let mut queue = InputQueue::default();
+
+        // Left would reverse the snake, but it comes after Up so it makes a U-turn instead.
+        assert!(queue.push(SnakeState::Up, game.snakes[0].heading));
+        assert!(queue.push(SnakeState::Left, game.snakes[0].heading));
+
+        let mut rng = GameRng::new(0);
+
+        for _ in 0..2 {
+            let direction = queue.next(game.snakes[0].heading);
+            game.step(&[direction], &mut rng);
+        }
+
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![Position(4, 6), Position(5, 6)]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn queued_turns_are_checked_against_the_last_one_queued() {
+        let mut queue = InputQueue::default();
+
+        assert!(!queue.push(SnakeState::Left, SnakeState::Right));
+        assert!(!queue.push(SnakeState::Right, SnakeState::Right));
+        assert!(queue.push(SnakeState::Up, SnakeState::Right));
+        assert!(!queue.push(SnakeState::Down, SnakeState::Right));
+        assert!(!queue.push(SnakeState::Up, SnakeState::Right));
+        assert!(queue.push(SnakeState::Left, SnakeState::Right));
+
+        assert_eq!(queue.next(SnakeState::Right), SnakeState::Up);
+        assert_eq!(queue.next(SnakeState::Up), SnakeState::Left);
+        assert_eq!(queue.next(SnakeState::Left), SnakeState::Left);
+    }
+
+    #[test]
+    fn turns_past_the_capacity_are_dropped() {
+        let mut queue = InputQueue::default();
+        let turns = [
+            SnakeState::Up,
+            SnakeState::Left,
+            SnakeState::Down,
+            SnakeState::Right,
+        ];
+
+        let accepted = turns
+            .iter()
+            .map(|&direction| queue.push(direction, SnakeState::Right))
+            .collect::<Vec<bool>>();
+
+        assert_eq!(accepted, vec![true, true, true, false]);
+        assert_eq!(queue.len(), InputQueue::CAPACITY);
+    }
+
+    #[test]
+    fn eating_scores_and_grows_the_snake() {
+        let mut game = game_with(&[&[(2, 5), (1, 5)]]);
+        game.food = food(3, 5, FoodKind::Normal);
+        let mut rng = GameRng::new(0);
+
+        let outcomes = game.step(&[SnakeState::Right], &mut rng);
+        assert_eq!(outcomes, vec![StepOutcome::Ate(FoodKind::Normal)]);
+        assert_eq!(game.snakes[0].score, 1);
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![Position(3, 5), Position(2, 5), Position(1, 5)]
+        );
+
+        let new_food = game.food.expect("new food is spawned");
+        assert!(!game.snakes[0].segments.contains(&new_food.position));
+
+        game.food = None;
+        game.step(&[SnakeState::Right], &mut rng);
+        assert_eq!(game.snakes[0].segments.len(), 3);
+    }
+
+    #[test]
+    fn feast_grows_the_snake_over_several_ticks() {
+        let mut game = game_with(&[&[(2, 5), (1, 5)]]);
+        game.food = food(3, 5, FoodKind::Feast);
+        let mut rng = GameRng::new(0);
+
+        game.step(&[SnakeState::Right], &mut rng);
+        game.food = None;
+        assert_eq!(game.snakes[0].score, 2);
+        assert_eq!(game.snakes[0].growth, 3);
+
+        for _ in 0..4 {
+            game.step(&[SnakeState::Up], &mut rng);
+        }
+
+        assert_eq!(game.snakes[0].segments.len(), 6);
+        assert_eq!(game.snakes[0].growth, 0);
+    }
+
+    #[test]
+    fn moving_onto_the_same_tile_kills_both_snakes() {
+        let mut game = game_with(&[&[(3, 5), (2, 5)], &[(5, 5), (6, 5)]]);
+        game.snakes[1].heading = SnakeState::Left;
+
+        let outcomes = game.step(&[SnakeState::Right, SnakeState::Left], &mut GameRng::new(0));
+        assert_eq!(
+            outcomes,
+            vec![
+                StepOutcome::Died(DeathCause::HeadOn),
+                StepOutcome::Died(DeathCause::HeadOn)
+            ]
+        );
+        assert!(game.is_over());
+    }
+
+    #[test]
+    fn running_into_another_snake_kills_only_the_mover() {
+        let mut game = game_with(&[&[(3, 5), (2, 5)], &[(4, 6), (4, 5), (4, 4)]]);
+        game.snakes[1].heading = SnakeState::Up;
+
+        let outcomes = game.step(&[SnakeState::Right, SnakeState::Up], &mut GameRng::new(0));
+        assert_eq!(
+            outcomes,
+            vec![
+                StepOutcome::Died(DeathCause::OtherSnake),
+                StepOutcome::Moved
+            ]
+        );
+    }
+
+    #[test]
+    fn dead_snakes_do_not_block() {
+        let mut game = game_with(&[&[(3, 5), (2, 5)], &[(4, 6), (4, 5), (4, 4)]]);
+        game.snakes[1].death = Some(DeathCause::Wall);
+
+        let outcomes = game.step(&[SnakeState::Right, SnakeState::Up], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Moved, StepOutcome::Dead]);
+    }
+
+    #[test]
+    fn shrinking_keeps_at_least_two_segments() {
+        let mut game = game_with(&[&[(4, 5), (3, 5), (2, 5), (1, 5)]]);
+        game.food = food(5, 5, FoodKind::Shrink);
+
+        let outcomes = game.step(&[SnakeState::Right], &mut GameRng::new(0));
+        assert_eq!(outcomes, vec![StepOutcome::Ate(FoodKind::Shrink)]);
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![Position(5, 5), Position(4, 5)]
+        );
+        assert_eq!(game.snakes[0].growth, 0);
+    }
+
+    #[test]
+    fn shrinking_takes_segments_off_the_tail() {
+        let mut game = game_with(&[&[(7, 5), (6, 5), (5, 5), (4, 5), (3, 5), (2, 5), (1, 5)]]);
+        game.food = food(8, 5, FoodKind::Shrink);
+
+        game.step(&[SnakeState::Right], &mut GameRng::new(0));
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![
+                Position(8, 5),
+                Position(7, 5),
+                Position(6, 5),
+                Position(5, 5)
+            ]
+        );
+    }
+
+    #[test]
+    fn uneaten_food_expires() {
+        let mut game = game_with(&[&[(2, 5), (1, 5)]]);
+        game.food = Some(Food {
+            position: Position(0, 0),
+            kind: FoodKind::Bonus,
+            ticks_left: Some(2),
+        });
+        let mut rng = GameRng::new(0);
+
+        game.step(&[SnakeState::Right], &mut rng);
+        assert_eq!(
+            game.food,
+            Some(Food {
+                position: Position(0, 0),
+                kind: FoodKind::Bonus,
+                ticks_left: Some(1),
+            })
+        );
+
+        // Only normal food spawns under the default rules, and it never expires.
+        game.step(&[SnakeState::Right], &mut rng);
+        let new_food = game.food.expect("new food is spawned");
+        assert_eq!(new_food.kind, FoodKind::Normal);
+        assert_eq!(new_food.ticks_left, None);
+    }
+
+    #[test]
+    fn speed_food_wears_off() {
+        let mut game = game_with(&[&[(2, 5), (1, 5)]]);
+        game.food = food(3, 5, FoodKind::SpeedUp);
+        let mut rng = GameRng::new(0);
+
+        game.step(&[SnakeState::Right], &mut rng);
+        game.food = None;
+        assert_eq!(game.tick_percent(), 60);
+
+        for _ in 0..FoodKind::SpeedUp.effect().duration {
+            game.step(&[SnakeState::Up], &mut rng);
+        }
+
+        assert_eq!(game.tick_percent(), 100);
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_the_same_game() {
+        let rules = GameRules {
+            players: 2,
+            boundary: BoundaryMode::Wrap,
+            food_weights: FoodKind::ALL.iter().map(|&kind| (kind, 1)).collect(),
+            ..Default::default()
+        };
+        let play = |seed: u64| {
+            let mut rng = GameRng::new(seed);
+            let mut game = GameState::new(rules.clone(), &mut rng);
+            let mut history = Vec::new();
+
+            for tick in 0..200 {
+                let inputs = [SnakeState::ALL[tick / 7 % 4], SnakeState::ALL[tick / 5 % 4]];
+                let outcomes = game.step(&inputs, &mut rng);
+                history.push((outcomes, game.snakes.clone(), game.food, game.speed));
+            }
+
+            history
+        };
+
+        assert_eq!(play(7), play(7));
+    }
+
+    #[test]
+    fn spawns_are_spread_along_the_middle_column() {
+        let rules = GameRules {
+            players: 2,
+            initial_length: 3,
+            ..Default::default()
+        };
+        let game = GameState::new(rules, &mut GameRng::new(0));
+
+        assert_eq!(
+            game.snakes[0].segments,
+            vec![Position(7, 3), Position(6, 3), Position(5, 3)]
+        );
+        assert_eq!(game.snakes[1].head(), Position(7, 11));
+        assert!(game.snakes.iter().all(Snake::is_alive));
+    }
+
+    #[test]
+    fn speed_curves() {
+        let curve = |shape: CurveShape, rate: u32, step: u32, floor: u32| SpeedCurve {
+            shape,
+            rate,
+            step,
+            floor,
+            ..Default::default()
+        };
+
+        let constant = curve(CurveShape::Constant, 10, 1, 0);
+        assert_eq!(constant.percent(0), 100);
+        assert_eq!(constant.percent(1000), 100);
+
+        let linear = curve(CurveShape::Linear, 10, 2, 50);
+        assert_eq!(linear.percent(0), 100);
+        assert_eq!(linear.percent(1), 95);
+        assert_eq!(linear.percent(4), 80);
+        assert_eq!(linear.percent(1000), 50);
+
+        let stepped = curve(CurveShape::Stepped, 10, 2, 0);
+        assert_eq!(stepped.percent(1), 100);
+        assert_eq!(stepped.percent(2), 90);
+        assert_eq!(stepped.percent(3), 90);
+        assert_eq!(stepped.percent(1000), 0);
+
+        let exponential = curve(CurveShape::Exponential, 50, 1, 10);
+        assert_eq!(exponential.percent(1), 50);
+        assert_eq!(exponential.percent(2), 25);
+        assert_eq!(exponential.percent(1000), 10);
+
+        // The curve starts from `start`, and a floor above it holds the curve there.
+        let slow_start = SpeedCurve {
+            start: 80,
+            ..linear
+        };
+        assert_eq!(slow_start.percent(0), 80);
+        assert_eq!(slow_start.percent(2), 72);
+        assert_eq!(slow_start.percent(1000), 50);
+
+        let high_floor = SpeedCurve {
+            floor: 90,
+            ..slow_start
+        };
+        assert_eq!(high_floor.percent(2), 80);
+    }
+}
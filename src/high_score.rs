@@ -0,0 +1,116 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    /// Seconds since the Unix epoch.
+    pub date: u64,
+    pub arena_width: u32,
+    pub arena_height: u32,
+    pub tick_interval: f64,
+}
+
+impl HighScore {
+    pub fn now(
+        name: String,
+        score: u32,
+        arena_width: u32,
+        arena_height: u32,
+        tick_interval: f64,
+    ) -> Self {
+        HighScore {
+            name,
+            score,
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+            arena_width,
+            arena_height,
+            tick_interval,
+        }
+    }
+
+    /// The date as `YYYY-MM-DD`, in UTC.
+    pub fn formatted_date(&self) -> String {
+        // Converts days since the epoch to a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let days = (self.date / 86400) as i64 + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The best scores so far, highest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub const CAPACITY: usize = 10;
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|directory| directory.join("snake").join("high_scores.toml"))
+    }
+
+    /// Reads the table from a TOML file, treating a missing file as an empty table.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|error| io::Error::new(ErrorKind::InvalidData, error.to_string())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let contents = toml::to_string(self)
+            .map_err(|error| io::Error::new(ErrorKind::InvalidData, error.to_string()))?;
+
+        fs::write(path, contents)
+    }
+
+    /// Adds a score to the table, returning its rank if it made it in.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        // Ties keep the older entry first.
+        let rank = self
+            .entries
+            .iter()
+            .position(|existing| existing.score < entry.score)
+            .unwrap_or(self.entries.len());
+
+        if rank >= Self::CAPACITY {
+            return None;
+        }
+
+        self.entries.insert(rank, entry);
+        self.entries.truncate(Self::CAPACITY);
+
+        Some(rank)
+    }
+}
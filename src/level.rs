@@ -0,0 +1,173 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+use crate::{
+    game::{Arena, FoodKind, GameRules, Position},
+    names::{parse_name, parse_number},
+};
+
+/// An arena layout read from an ASCII map, where `#` is a wall, `S` is where a snake's head starts
+/// and `.` is an empty floor tile. The first line of the map is the top row of the arena, and
+/// spawns are handed out to players in reading order. Lines like `food bonus 3` set the spawn
+/// weight of a kind of food, replacing the configured weights.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub arena: Arena,
+    pub walls: Vec<Position>,
+    pub spawns: Vec<Position>,
+    pub food_weights: Vec<(FoodKind, u32)>,
+}
+
+impl Level {
+    pub fn parse(map: &str) -> io::Result<Self> {
+        let (settings, rows) = map
+            .lines()
+            .map(str::trim_end)
+            .filter(|row| !row.is_empty())
+            .partition::<Vec<&str>, _>(|row| row.starts_with("food "));
+        let width = rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        if width == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the map is empty"));
+        }
+
+        let mut level = Level {
+            arena: Arena(width as u32, rows.len() as u32),
+            walls: Vec::new(),
+            spawns: Vec::new(),
+            food_weights: Vec::new(),
+        };
+
+        for setting in settings {
+            let mut words = setting.split_whitespace().skip(1);
+
+            level.food_weights.push((
+                parse_name("food", words.next().unwrap_or_default())?,
+                parse_number(words.next())?,
+            ));
+        }
+
+        for (row_index, row) in rows.iter().enumerate() {
+            let y = (rows.len() - 1 - row_index) as i32;
+
+            for (x, tile) in row.chars().enumerate() {
+                let position = Position(x as i32, y);
+
+                match tile {
+                    '.' => {}
+                    '#' => level.walls.push(position),
+                    'S' => level.spawns.push(position),
+                    _ => {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            format!("unknown tile `{}` on line {}", tile, row_index + 1),
+                        ))
+                    }
+                }
+            }
+        }
+
+        Ok(level)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Replaces the arena of `rules` with this level's, failing if it has fewer spawns than there
+    /// are players.
+    pub fn apply(self, rules: &mut GameRules) -> io::Result<()> {
+        if self.spawns.len() < rules.players.max(1) as usize {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the level only has {} `S` tiles for {} players",
+                    self.spawns.len(),
+                    rules.players
+                ),
+            ));
+        }
+
+        rules.arena = self.arena;
+        rules.walls = self.walls;
+        rules.spawns = self.spawns;
+
+        if !self.food_weights.is_empty() {
+            rules.food_weights = self.food_weights;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_are_read_top_row_first() {
+        let level = Level::parse("####\n#S.#\n#..S\n####\n").unwrap();
+
+        assert_eq!(level.arena, Arena(4, 4));
+        assert_eq!(level.spawns, vec![Position(1, 2), Position(3, 1)]);
+        assert_eq!(level.walls.len(), 11);
+        assert!(level.walls.contains(&Position(0, 3)));
+        assert!(level.walls.contains(&Position(3, 2)));
+        assert!(!level.walls.contains(&Position(3, 1)));
+        assert!(level.food_weights.is_empty());
+    }
+
+    #[test]
+    fn ragged_rows_are_padded_with_floor() {
+        let level = Level::parse("#\n.S..\n\n#.\n").unwrap();
+
+        assert_eq!(level.arena, Arena(4, 3));
+        assert_eq!(level.walls, vec![Position(0, 2), Position(0, 0)]);
+        assert_eq!(level.spawns, vec![Position(1, 1)]);
+    }
+
+    #[test]
+    fn food_lines_set_the_weights() {
+        let level = Level::parse("food bonus 3\n..S\nfood normal 10\n...\n").unwrap();
+
+        assert_eq!(level.arena, Arena(3, 2));
+        assert_eq!(
+            level.food_weights,
+            vec![(FoodKind::Bonus, 3), (FoodKind::Normal, 10)]
+        );
+    }
+
+    #[test]
+    fn broken_maps_are_rejected() {
+        assert!(Level::parse("").is_err());
+        assert!(Level::parse("food bonus 3\n").is_err());
+        assert!(Level::parse("..x\n").is_err());
+        assert!(Level::parse("food pizza 3\n..S\n").is_err());
+        assert!(Level::parse("food bonus lots\n..S\n").is_err());
+    }
+
+    #[test]
+    fn applying_a_level_needs_a_spawn_per_player() {
+        let level = Level::parse("food ghost 2\nS.#\n..S\n").unwrap();
+        let mut rules = GameRules {
+            players: 2,
+            ..Default::default()
+        };
+
+        level.clone().apply(&mut rules).unwrap();
+        assert_eq!(rules.arena, Arena(3, 2));
+        assert_eq!(rules.walls, level.walls);
+        assert_eq!(rules.spawns, level.spawns);
+        assert_eq!(rules.food_weights, vec![(FoodKind::Ghost, 2)]);
+
+        rules.players = 3;
+        assert!(level.apply(&mut rules).is_err());
+    }
+}
@@ -1,351 +1,1105 @@
-use std::ops::Sub;
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
-use bevy::{core::FixedTimestep, prelude::*};
-use rand::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 
-struct Arena(u32, u32);
+pub mod ai;
+pub mod batch;
+mod broadcast;
+pub mod cli;
+mod client;
+pub mod config;
+pub mod env;
+pub mod game;
+pub mod high_score;
+pub mod level;
+mod names;
+pub mod net;
+#[cfg(feature = "python")]
+mod python;
+pub mod replay;
+pub mod results;
+pub mod rng;
+mod sprites;
+pub mod theme;
+mod ui;
+
+pub use ai::{BotKind, GreedyBot, HamiltonianBot, SnakeController};
+pub use batch::BatchEnv;
+pub use broadcast::Broadcast;
+pub use client::Connection;
+pub use config::{Difficulty, SnakeConfig};
+pub use env::{EnvConfig, Observation, ObservationKind, RewardShaping, SnakeEnv, StepInfo};
+pub use game::{
+    Arena, BoundaryMode, CurveShape, DeathCause, FoodEffect, FoodKind, GameRules, GameState,
+    InputQueue, Position, Snake, SnakeState, SpeedCurve, SpeedMeasure, StepOutcome,
+};
+pub use high_score::{HighScore, HighScores};
+pub use level::Level;
+pub use replay::Replay;
+pub use results::GameResults;
+pub use rng::GameRng;
+pub use theme::{SnakeColours, Theme, ThemeWatcher};
 
 struct Size(u32, u32);
 
 struct Materials {
-    snake: Handle<ColorMaterial>,
-    food: Handle<ColorMaterial>,
+    food: HashMap<FoodKind, Handle<ColorMaterial>>,
+    wall: Handle<ColorMaterial>,
+    grid: Handle<ColorMaterial>,
+}
+
+struct SnakeHead(SnakeState);
+
+struct SnakeSegment;
+
+/// Where a snake segment was before the last tick, so it can be drawn sliding to its `Position`.
+struct PreviousPosition(Position);
+
+/// Identifies the entity of a player's snake by its index in `GameState::snakes`. The entity also
+/// holds what steers the snake, either the player's `InputQueue` or a `Bot`.
+pub struct Player(pub usize);
+
+/// Steers a player's snake in place of the keyboard.
+pub struct Bot(pub Box<dyn SnakeController>);
+
+/// The keys that turn a player's snake.
+struct InputBinding([(KeyCode, SnakeState); 4]);
+
+const INPUT_BINDINGS: [[(KeyCode, SnakeState); 4]; 2] = [
+    [
+        (KeyCode::Left, SnakeState::Left),
+        (KeyCode::Right, SnakeState::Right),
+        (KeyCode::Up, SnakeState::Up),
+        (KeyCode::Down, SnakeState::Down),
+    ],
+    [
+        (KeyCode::A, SnakeState::Left),
+        (KeyCode::D, SnakeState::Right),
+        (KeyCode::W, SnakeState::Up),
+        (KeyCode::S, SnakeState::Down),
+    ],
+];
+
+/// The shades a player's snake is drawn in as coloured squares, the head first and then
+/// `BODY_SHADES` steps along the gradient of its body, followed by the same again faded for once
+/// the snake has died. Sprites are tinted instead.
+struct SnakeShades(Vec<Handle<ColorMaterial>>);
+
+const BODY_SHADES: usize = 8;
+
+/// A floor tile, drawn in the grid colour of the theme.
+struct GridTile;
+
+/// Reloads the theme when its file changes, looking every so often.
+struct ThemeReload {
+    watcher: ThemeWatcher,
+    timer: Timer,
+}
+
+/// The segment entities of a player's snake, head first.
+struct SnakeSegments(Vec<Entity>);
+
+struct Food;
+
+/// The camera the arena is drawn with, as opposed to the one for the UI.
+struct GameCamera;
+
+struct Wall;
+
+/// Times the moves of the snake, only advancing while the game is being played.
+struct TickTimer(Timer);
+
+/// Seconds between moves of the snakes.
+pub struct TickInterval {
+    /// The interval the speed curve starts from, taken from the config. Changing it takes effect
+    /// straight away.
+    pub base: f64,
+    /// The interval in use right now, following the speed curve of the rules and any food eaten.
+    pub current: f64,
 }
 
-#[derive(PartialEq, Clone, Copy)]
-enum SnakeState {
-    Left,
-    Right,
-    Down,
-    Up,
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AppState {
+    Menu,
+    Playing,
+    Paused,
+    GameOver,
 }
 
-#[derive(Clone, Copy)]
-struct LatestState(SnakeState);
+/// The running tally of the current game, reset whenever the game ends.
+#[derive(Default)]
+pub struct Score {
+    /// The points scored by each player, which depend on the kind of food eaten.
+    pub eaten: Vec<u32>,
+    pub elapsed: f64,
+}
+
+/// Records the directions fed into every tick, saving the replay to `path` whenever the game ends.
+pub struct ReplayRecorder {
+    path: PathBuf,
+    replay: Replay,
+    tick: u64,
+    /// The direction each player had on the last tick, so only changes are recorded.
+    directions: Vec<SnakeState>,
+}
 
-impl LatestState {
-    fn get_opposite(snake_state: SnakeState) -> SnakeState {
-        match snake_state {
-            SnakeState::Left => SnakeState::Right,
-            SnakeState::Right => SnakeState::Left,
-            SnakeState::Down => SnakeState::Up,
-            SnakeState::Up => SnakeState::Down,
+impl ReplayRecorder {
+    pub fn new(path: PathBuf, replay: Replay) -> Self {
+        ReplayRecorder {
+            path,
+            replay,
+            tick: 0,
+            directions: Vec::new(),
         }
     }
 
-    fn switch(&mut self, new_state: SnakeState, compared_to: SnakeState) {
-        if Self::get_opposite(compared_to) != new_state {
-            self.0 = new_state;
+    /// Records the directions fed into the next tick.
+    fn record(&mut self, directions: &[SnakeState]) {
+        for (player, &direction) in directions.iter().enumerate() {
+            let previous = self
+                .directions
+                .get(player)
+                .copied()
+                .unwrap_or(Replay::FIRST_DIRECTION);
+
+            if direction != previous {
+                self.replay.inputs.push((self.tick, player, direction));
+            }
         }
+
+        self.directions.clear();
+        self.directions.extend_from_slice(directions);
+        self.tick += 1;
     }
 }
 
-struct SnakeHead(SnakeState);
+/// Feeds a recorded replay back into the game in place of the keyboard.
+pub struct ReplayPlayer {
+    replay: Replay,
+    tick: u64,
+    /// The index of the first input not yet played.
+    next_input: usize,
+    /// The direction of each player as of the last tick played.
+    directions: Vec<SnakeState>,
+}
 
-struct SnakeSegment;
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            tick: 0,
+            next_input: 0,
+            directions: Vec::new(),
+        }
+    }
 
-struct SnakeSegments(Vec<Entity>);
+    /// Overwrites `directions` with those recorded for the next tick.
+    fn play(&mut self, directions: &mut [SnakeState]) {
+        self.directions
+            .resize(directions.len(), Replay::FIRST_DIRECTION);
 
-#[derive(PartialEq, Clone, Copy)]
-struct Position(i32, i32);
+        while let Some(&(tick, player, direction)) = self.replay.inputs.get(self.next_input) {
+            if tick > self.tick {
+                break;
+            }
 
-impl Sub for Position {
-    type Output = Position;
+            if let Some(current) = self.directions.get_mut(player) {
+                *current = direction;
+            }
 
-    fn sub(self, rhs: Self) -> Self::Output {
-        Position(self.0 - rhs.0, self.1 - rhs.1)
+            self.next_input += 1;
+        }
+
+        directions.copy_from_slice(&self.directions);
+        self.tick += 1;
     }
 }
 
-struct Food;
+/// The replay being played back or recorded, if there is one.
+#[derive(SystemParam)]
+pub struct Replays<'a> {
+    player: Option<ResMut<'a, ReplayPlayer>>,
+    recorder: Option<ResMut<'a, ReplayRecorder>>,
+}
 
-pub struct GrowthEvent;
+/// Sent with the player whose snake ate.
+pub struct GrowthEvent(pub usize);
 pub struct FoodEvent;
+/// Sent once every snake has died.
 pub struct GameOverEvent;
 
-struct LastTailPosition(Option<Position>);
+/// The events sent by `move_snake` about what happened on a tick.
+#[derive(SystemParam)]
+pub struct TickEvents<'a> {
+    growth: EventWriter<'a, GrowthEvent>,
+    food: EventWriter<'a, FoodEvent>,
+    game_over: EventWriter<'a, GameOverEvent>,
+}
 
-fn update_latest_state(
-    input: Res<Input<KeyCode>>,
-    mut latest_state: ResMut<LatestState>,
-    head: Query<&SnakeHead>,
-) {
-    let head_state = head.iter().next().unwrap().0;
+/// Everything that steers the snakes, which are the keyboard and the bots.
+#[derive(SystemParam)]
+pub struct Controllers<'a> {
+    queues: Query<'a, (&'static Player, &'static mut InputQueue)>,
+    bots: Query<'a, (&'static Player, &'static mut Bot)>,
+}
 
-    if input.pressed(KeyCode::Left) {
-        latest_state.switch(SnakeState::Left, head_state);
-    }
+impl<'a> Controllers<'a> {
+    /// The directions to feed into the next tick. Snakes without a controller, or that have died,
+    /// keep going straight.
+    fn directions(&mut self, game: &GameState) -> Vec<SnakeState> {
+        let mut directions = game
+            .snakes
+            .iter()
+            .map(|snake| snake.heading)
+            .collect::<Vec<SnakeState>>();
 
-    if input.pressed(KeyCode::Right) {
-        latest_state.switch(SnakeState::Right, head_state);
-    }
+        let is_alive = |player: &Player| game.snakes.get(player.0).is_some_and(Snake::is_alive);
 
-    if input.pressed(KeyCode::Up) {
-        latest_state.switch(SnakeState::Up, head_state);
+        for (player, mut queue) in self.queues.iter_mut() {
+            if is_alive(player) {
+                directions[player.0] = queue.next_direction(game, player.0);
+            }
+        }
+
+        for (player, mut bot) in self.bots.iter_mut() {
+            if is_alive(player) {
+                directions[player.0] = bot.0.next_direction(game, player.0);
+            }
+        }
+
+        directions
     }
+}
 
-    if input.pressed(KeyCode::Down) {
-        latest_state.switch(SnakeState::Down, head_state);
+fn queue_input(
+    input: Res<Input<KeyCode>>,
+    game: Res<GameState>,
+    mut players: Query<(&Player, &InputBinding, &mut InputQueue)>,
+) {
+    for (player, binding, mut queue) in players.iter_mut() {
+        let heading = match game.snakes.get(player.0) {
+            Some(snake) => snake.heading,
+            None => continue,
+        };
+
+        for &(key, direction) in &binding.0 {
+            if input.just_pressed(key) {
+                queue.push(direction, heading);
+            }
+        }
     }
 }
 
+fn update_tick_interval(
+    game: Res<GameState>,
+    mut interval: ResMut<TickInterval>,
+    mut timer: ResMut<TickTimer>,
+) {
+    interval.current = interval.base * game.tick_percent() as f64 / 100.0;
+    timer
+        .0
+        .set_duration(Duration::from_secs_f64(interval.current));
+}
+
 fn move_snake(
-    arena: Res<Arena>,
-    mut game_over_writer: EventWriter<GameOverEvent>,
-    mut last_position: ResMut<LastTailPosition>,
-    segments: ResMut<SnakeSegments>,
-    latest_state: Res<LatestState>,
-    mut heads: Query<(Entity, &mut SnakeHead)>,
-    mut positions: Query<&mut Position>,
+    time: Res<Time>,
+    mut timer: ResMut<TickTimer>,
+    mut events: TickEvents,
+    mut rng: ResMut<GameRng>,
+    mut game: ResMut<GameState>,
+    mut controllers: Controllers,
+    mut replays: Replays,
 ) {
-    for (entity, mut head) in heads.iter_mut() {
-        let segment_positions = segments
-            .0
-            .iter()
-            .map(|&entity| *positions.get_mut(entity).unwrap())
-            .collect::<Vec<Position>>();
+    // A frame that took longer than a tick, as when the window is dragged, runs every tick that
+    // fell within it, so the game keeps time with the interval.
+    let ticks = timer.0.tick(time.delta()).times_finished();
 
-        // This part must be done before the head is moved, otherwise two segments would be in the same spot.
-        last_position.0 = Some(*segment_positions.last().unwrap());
+    for _ in 0..ticks {
+        let mut directions = controllers.directions(&game);
 
-        let mut head_position = positions.get_mut(entity).unwrap();
-        head.0 = latest_state.0;
+        if let Some(replay_player) = &mut replays.player {
+            replay_player.play(&mut directions);
+        }
 
-        match head.0 {
-            SnakeState::Left => head_position.0 -= 1,
-            SnakeState::Right => head_position.0 += 1,
-            SnakeState::Down => head_position.1 -= 1,
-            SnakeState::Up => head_position.1 += 1,
+        if let Some(recorder) = &mut replays.recorder {
+            recorder.record(&directions);
         }
 
-        if segment_positions.contains(&head_position)
-            || head_position.0 < 0
-            || head_position.1 < 0
-            || head_position.0 as u32 >= arena.0
-            || head_position.1 as u32 >= arena.1
-        {
-            game_over_writer.send(GameOverEvent);
+        let outcomes = game.step(&directions, &mut *rng);
+
+        let mut eaten = false;
+
+        for (index, &outcome) in outcomes.iter().enumerate() {
+            if let StepOutcome::Ate(_) = outcome {
+                events.growth.send(GrowthEvent(index));
+                eaten = true;
+            }
         }
 
-        segment_positions
-            .iter()
-            // Skips the head since we already moved it. This way every segment moves to the position of the one in front of it.
-            .zip(segments.0.iter().skip(1))
-            .for_each(|(&position, &segment)| *positions.get_mut(segment).unwrap() = position);
+        if eaten {
+            events.food.send(FoodEvent);
+        }
+
+        if game.is_over() {
+            events.game_over.send(GameOverEvent);
+            break;
+        }
     }
 }
 
-fn update_transform_position(
-    windows: Res<Windows>,
-    arena: Res<Arena>,
-    mut positions: Query<(&Position, &mut Transform)>,
+fn update_score(
+    time: Res<Time>,
+    game: Res<GameState>,
+    mut growth_reader: EventReader<GrowthEvent>,
+    mut score: ResMut<Score>,
 ) {
-    fn update_axis(position: f32, length: f32, subdivisions: f32) -> f32 {
-        let tile_size = length / subdivisions;
+    for &GrowthEvent(player) in growth_reader.iter() {
+        if score.eaten.len() <= player {
+            score.eaten.resize(player + 1, 0);
+        }
 
-        position * tile_size - length / 2.0 + tile_size / 2.0
+        score.eaten[player] = game.snakes.get(player).map_or(0, |snake| snake.score);
     }
 
-    let window = windows.get_primary().unwrap();
-
-    for (position, mut transform) in positions.iter_mut() {
-        transform.translation.x = update_axis(position.0 as f32, window.width(), arena.0 as f32);
-        transform.translation.y = update_axis(position.1 as f32, window.height(), arena.1 as f32);
-    }
+    score.elapsed += time.delta_seconds_f64();
 }
 
-fn update_size(windows: Res<Windows>, arena: Res<Arena>, mut sprites: Query<(&Size, &mut Sprite)>) {
-    let window = windows.get_primary().unwrap();
+fn game_over(
+    mut reader: EventReader<GameOverEvent>,
+    mut state: ResMut<State<AppState>>,
+    recorder: Option<Res<ReplayRecorder>>,
+) {
+    if reader.iter().next().is_some() {
+        if let Some(recorder) = recorder {
+            if let Err(error) = recorder.replay.save(&recorder.path) {
+                error!(
+                    "Failed to save replay to {}: {}",
+                    recorder.path.display(),
+                    error
+                );
+            }
+        }
 
-    for (size, mut sprite) in sprites.iter_mut() {
-        sprite.size = Vec2::new(
-            window.width() / arena.0 as f32 * size.0 as f32,
-            window.height() / arena.1 as f32 * size.1 as f32,
-        );
+        // Pausing on the same tick can have queued a transition already, which the end of the game
+        // takes the place of.
+        state.overwrite_set(AppState::GameOver).unwrap();
     }
 }
 
-fn snake_eat(
-    mut growth_writer: EventWriter<GrowthEvent>,
+fn start_game(
     mut food_writer: EventWriter<FoodEvent>,
-    heads: Query<&Position, With<SnakeHead>>,
-    food: Query<(Entity, &Position), With<Food>>,
-    mut commands: Commands,
+    mut queues: Query<&mut InputQueue>,
+    mut rng: ResMut<GameRng>,
+    mut game: ResMut<GameState>,
+    mut score: ResMut<Score>,
+    mut timer: ResMut<TickTimer>,
 ) {
-    for head_position in heads.iter() {
-        for (food, food_position) in food.iter() {
-            if head_position == food_position {
-                commands.entity(food).despawn();
-                growth_writer.send(GrowthEvent);
-                food_writer.send(FoodEvent);
-            }
-        }
+    game.reset(&mut *rng);
+    *score = Score {
+        eaten: vec![0; game.snakes.len()],
+        ..Default::default()
+    };
+
+    for mut queue in queues.iter_mut() {
+        queue.clear();
     }
+
+    timer.0.reset();
+    food_writer.send(FoodEvent);
+}
+
+/// Starts the next game straight away, for when there is nobody to show a game over screen to.
+fn restart(mut state: ResMut<State<AppState>>) {
+    state.set(AppState::Playing).unwrap();
 }
 
 fn spawn_segment(
     material: Handle<ColorMaterial>,
-    mut commands: Commands,
+    sprites: Option<&sprites::SnakeSprites>,
+    commands: &mut Commands,
     position: Position,
 ) -> Entity {
-    commands
-        .spawn_bundle(SpriteBundle {
+    let mut segment = commands.spawn();
+
+    match sprites {
+        Some(sprites) => segment.insert_bundle(SpriteSheetBundle {
+            texture_atlas: sprites.atlas.clone(),
+            ..Default::default()
+        }),
+        None => segment.insert_bundle(SpriteBundle {
             material,
             ..Default::default()
-        })
+        }),
+    };
+
+    segment
         .insert(position)
+        .insert(PreviousPosition(position))
         .insert(Size(1, 1))
         .insert(SnakeSegment)
         .id()
 }
 
-fn grow_snake(
-    mut segments: ResMut<SnakeSegments>,
-    materials: Res<Materials>,
-    last_position: ResMut<LastTailPosition>,
-    commands: Commands,
-    mut growth_reader: EventReader<GrowthEvent>,
+fn sync_snakes(
+    game: Res<GameState>,
+    sprites: Option<Res<sprites::SnakeSprites>>,
+    mut commands: Commands,
+    mut players: Query<(&Player, &SnakeShades, &mut SnakeSegments)>,
+    mut heads: Query<&mut SnakeHead>,
+    mut positions: Query<(&mut Position, &mut PreviousPosition)>,
 ) {
-    if growth_reader.iter().next().is_some() {
-        segments.0.push(spawn_segment(
-            materials.snake.clone(),
-            commands,
-            last_position.0.unwrap(),
-        ))
+    if !game.is_changed() {
+        return;
+    }
+
+    for (player, shades, mut segments) in players.iter_mut() {
+        let snake = match game.snakes.get(player.0) {
+            Some(snake) => snake,
+            None => continue,
+        };
+
+        // A snake gets shorter when it eats shrinking food, when its player leaves and when a new
+        // game starts, and the segments it lost are dropped from the tail.
+        let kept = snake.segments.len().min(segments.0.len());
+
+        for entity in segments.0.split_off(kept) {
+            commands.entity(entity).despawn();
+        }
+
+        for (index, &position) in snake.segments.iter().enumerate() {
+            match segments.0.get(index) {
+                Some(&entity) => {
+                    if let Ok((mut segment_position, mut previous)) = positions.get_mut(entity) {
+                        previous.0 = *segment_position;
+                        *segment_position = position;
+                    }
+                }
+                None => {
+                    let entity = spawn_segment(
+                        shades.0[0].clone(),
+                        sprites.as_deref(),
+                        &mut commands,
+                        position,
+                    );
+
+                    if index == 0 {
+                        commands.entity(entity).insert(SnakeHead(snake.heading));
+                    }
+
+                    segments.0.push(entity);
+                }
+            }
+        }
+
+        if let Some(mut head) = segments
+            .0
+            .first()
+            .and_then(|&entity| heads.get_mut(entity).ok())
+        {
+            head.0 = snake.heading;
+        }
     }
 }
 
-fn game_over(
-    food_writer: EventWriter<FoodEvent>,
-    arena: Res<Arena>,
-    segments: ResMut<SnakeSegments>,
+fn sync_food(
+    game: Res<GameState>,
     materials: Res<Materials>,
     mut commands: Commands,
-    mut reader: EventReader<GameOverEvent>,
-    entities: Query<Entity, With<Position>>,
-    mut latest_state: ResMut<LatestState>,
+    mut food: Query<(Entity, &mut Position, &mut Handle<ColorMaterial>), With<Food>>,
 ) {
-    if reader.iter().next().is_some() {
-        for entity in entities.iter() {
-            commands.entity(entity).despawn();
+    if !game.is_changed() {
+        return;
+    }
+
+    match (food.iter_mut().next(), game.food) {
+        (Some((_, mut position, mut material)), Some(new_food)) => {
+            *position = new_food.position;
+            *material = materials.food[&new_food.kind].clone();
+        }
+        (Some((entity, _, _)), None) => commands.entity(entity).despawn(),
+        (None, Some(new_food)) => {
+            // Food can spawn under the body of a dead snake, so it is drawn in front of snakes.
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: materials.food[&new_food.kind].clone(),
+                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                    ..Default::default()
+                })
+                .insert(new_food.position)
+                .insert(Size(1, 1))
+                .insert(Food);
         }
+        (None, None) => {}
+    }
+}
+
+/// Where the centre of a tile is along one axis of the window, in a possibly fractional `position`.
+fn update_axis(position: f32, length: f32, subdivisions: f32) -> f32 {
+    let tile_size = length / subdivisions;
 
-        latest_state.0 = SnakeState::Right;
-        spawn_snake(food_writer, arena, segments, materials, commands);
+    position * tile_size - length / 2.0 + tile_size / 2.0
+}
+
+/// How far along one axis a segment moved in a tick, taking the short way round when it wrapped
+/// past the edge of an arena `length` tiles long.
+fn wrapped_offset(from: i32, to: i32, length: u32, boundary: BoundaryMode) -> i32 {
+    let offset = to - from;
+
+    match boundary == BoundaryMode::Wrap && offset.abs() > 1 && offset.abs() == length as i32 - 1 {
+        true => -offset.signum(),
+        false => offset,
     }
 }
 
-fn spawn_snake(
-    mut food_writer: EventWriter<FoodEvent>,
-    arena: Res<Arena>,
-    mut segments: ResMut<SnakeSegments>,
-    materials: Res<Materials>,
-    mut commands: Commands,
+/// Where a segment is drawn `progress` of the way through a tick, in fractional tiles. Segments that
+/// did not move one tile, like those of a new game, are drawn where they are now.
+fn interpolated_position(
+    previous: Position,
+    position: Position,
+    rules: &GameRules,
+    progress: f32,
+) -> Vec2 {
+    let arena = rules.arena;
+    let offset = Position(
+        wrapped_offset(previous.0, position.0, arena.0, rules.boundary),
+        wrapped_offset(previous.1, position.1, arena.1, rules.boundary),
+    );
+
+    if offset.0.abs() + offset.1.abs() != 1 {
+        return Vec2::new(position.0 as f32, position.1 as f32);
+    }
+
+    // Going off one edge, a segment is drawn coming back in on the other once past the middle.
+    let along = |from: i32, offset: i32, length: u32| {
+        (from as f32 + offset as f32 * progress + 0.5).rem_euclid(length as f32) - 0.5
+    };
+
+    Vec2::new(
+        along(previous.0, offset.0, arena.0),
+        along(previous.1, offset.1, arena.1),
+    )
+}
+
+/// Places every sprite on its tile, sliding snake segments there over the course of each tick.
+fn update_transform_position(
+    windows: Res<Windows>,
+    game: Res<GameState>,
+    timer: Option<Res<TickTimer>>,
+    mut positions: Query<(&Position, Option<&PreviousPosition>, &mut Transform)>,
 ) {
-    let center = Position((arena.0 / 2) as i32, (arena.1 / 2) as i32);
+    let window = windows.get_primary().unwrap();
+    let arena = game.rules.arena;
+    // Without a tick timer, as when a server runs the game, there is no telling how far along a
+    // tick is, so the latest positions are drawn as they are.
+    let progress = timer.map_or(1.0, |timer| timer.0.percent());
 
-    segments.0 = vec![
-        commands
-            .spawn_bundle(SpriteBundle {
-                material: materials.snake.clone(),
-                ..Default::default()
-            })
-            .insert(center)
-            .insert(Size(1, 1))
-            .insert(SnakeHead(SnakeState::Right))
-            .id(),
-        spawn_segment(materials.snake.clone(), commands, center - Position(1, 0)),
-    ];
+    for (&position, previous, mut transform) in positions.iter_mut() {
+        let drawn = match previous {
+            Some(previous) => interpolated_position(previous.0, position, &game.rules, progress),
+            None => Vec2::new(position.0 as f32, position.1 as f32),
+        };
 
-    food_writer.send(FoodEvent);
+        transform.translation.x = update_axis(drawn.x, window.width(), arena.0 as f32);
+        transform.translation.y = update_axis(drawn.y, window.height(), arena.1 as f32);
+    }
 }
 
-fn generate_random_position(arena: Res<Arena>, taken: Vec<Position>) -> Position {
-    let mut rng = rand::thread_rng();
-    let mut all_positions = Vec::with_capacity(arena.0 as usize * arena.1 as usize);
+fn update_size(
+    windows: Res<Windows>,
+    game: Res<GameState>,
+    mut sprites: Query<(&Size, &mut Sprite)>,
+) {
+    let window = windows.get_primary().unwrap();
+    let arena = game.rules.arena;
 
-    for x in 0..arena.0 {
-        for y in 0..arena.1 {
-            all_positions.push(Position(x as i32, y as i32))
+    for (size, mut sprite) in sprites.iter_mut() {
+        sprite.size = Vec2::new(
+            window.width() / arena.0 as f32 * size.0 as f32,
+            window.height() / arena.1 as f32 * size.1 as f32,
+        );
+    }
+}
+
+fn setup_game(
+    mut commands: Commands,
+    config: Res<SnakeConfig>,
+    rules: Res<GameRules>,
+    mut rng: ResMut<GameRng>,
+) {
+    let game = GameState::new(rules.clone(), &mut *rng);
+    let humans = game.snakes.len().saturating_sub(config.bots as usize);
+
+    for index in 0..game.snakes.len() {
+        let mut player = commands.spawn();
+
+        player.insert(Player(index));
+
+        if index < humans {
+            player.insert(InputQueue::default());
+        } else {
+            player.insert(Bot(config.bot.controller(&rules)));
         }
     }
 
-    all_positions = all_positions
-        .iter()
-        .copied()
-        .filter(|&position| !taken.contains(&position))
-        .collect();
-    *all_positions.choose(&mut rng).unwrap()
+    commands.insert_resource(game);
 }
 
-fn spawn_food(
-    mut food_reader: EventReader<FoodEvent>,
-    arena: Res<Arena>,
-    materials: Res<Materials>,
-    positions: Query<&Position>,
+fn colour([red, green, blue]: [f32; 3]) -> Color {
+    Color::rgb(red, green, blue)
+}
+
+/// The colour of one of the `SnakeShades` of a player.
+fn shade_colour(theme: &Theme, player: usize, shade: usize) -> [f32; 3] {
+    if shade > BODY_SHADES {
+        return theme.faded(shade_colour(theme, player, shade - BODY_SHADES - 1));
+    }
+
+    match shade {
+        0 => theme.snake_colours(player).head,
+        _ => theme.body_colour(player, (shade - 1) as f32 / (BODY_SHADES - 1) as f32),
+    }
+}
+
+/// The one of the `SnakeShades` closest to the colour of a segment.
+fn shade_index(index: usize, length: usize) -> usize {
+    match index {
+        0 => 0,
+        _ => 1 + (theme::along_body(index, length) * (BODY_SHADES - 1) as f32).round() as usize,
+    }
+}
+
+fn setup(
     mut commands: Commands,
+    theme: Res<Theme>,
+    rules: Res<GameRules>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    if food_reader.iter().next().is_some() {
+    commands
+        .spawn_bundle(OrthographicCameraBundle::new_2d())
+        .insert(GameCamera);
+
+    let wall = materials.add(ColorMaterial::color(colour(theme.wall)));
+    let grid = materials.add(ColorMaterial::color(colour(
+        theme.grid.unwrap_or(theme.background),
+    )));
+
+    // Floor tiles sit behind everything else, a little smaller than a tile so the grid lines show.
+    for x in 0..rules.arena.0 as i32 {
+        for y in 0..rules.arena.1 as i32 {
+            commands
+                .spawn_bundle(SpriteBundle {
+                    material: grid.clone(),
+                    visible: Visible {
+                        is_visible: theme.grid.is_some(),
+                        is_transparent: true,
+                    },
+                    transform: Transform {
+                        translation: Vec3::new(0.0, 0.0, -1.0),
+                        scale: Vec3::new(0.9, 0.9, 1.0),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Position(x, y))
+                .insert(Size(1, 1))
+                .insert(GridTile);
+        }
+    }
+
+    for &position in &rules.walls {
         commands
             .spawn_bundle(SpriteBundle {
-                material: materials.food.clone(),
+                material: wall.clone(),
                 ..Default::default()
             })
-            .insert(generate_random_position(
-                arena,
-                positions.iter().copied().collect(),
-            ))
+            .insert(position)
             .insert(Size(1, 1))
-            .insert(Food);
+            .insert(Wall);
     }
-}
-
-fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
 
     commands.insert_resource(Materials {
-        snake: materials.add(ColorMaterial::color(Color::rgb(1.0, 0.0, 0.0))),
-        food: materials.add(ColorMaterial::color(Color::rgb(1.0, 1.0, 0.0))),
+        food: FoodKind::ALL
+            .iter()
+            .map(|&kind| {
+                let material = ColorMaterial::color(colour(theme.food_colour(kind)));
+                (kind, materials.add(material))
+            })
+            .collect(),
+        wall,
+        grid,
     });
-    commands.insert_resource(LatestState(SnakeState::Right));
-    commands.insert_resource(Arena(15, 15));
+}
+
+/// Brings everything already on screen in line with the theme whenever it changes.
+fn apply_theme(
+    theme: Res<Theme>,
+    materials: Res<Materials>,
+    mut clear_colour: ResMut<ClearColor>,
+    mut assets: ResMut<Assets<ColorMaterial>>,
+    players: Query<(&Player, &SnakeShades)>,
+    mut grid: Query<&mut Visible, With<GridTile>>,
+) {
+    if !theme.is_changed() {
+        return;
+    }
+
+    clear_colour.0 = colour(theme.background);
+
+    let mut set = |handle: &Handle<ColorMaterial>, new_colour: [f32; 3]| {
+        if let Some(material) = assets.get_mut(handle) {
+            material.color = colour(new_colour);
+        }
+    };
+
+    set(&materials.wall, theme.wall);
+    set(&materials.grid, theme.grid.unwrap_or(theme.background));
+
+    for (&kind, handle) in &materials.food {
+        set(handle, theme.food_colour(kind));
+    }
+
+    for (player, shades) in players.iter() {
+        for (shade, handle) in shades.0.iter().enumerate() {
+            set(handle, shade_colour(&theme, player.0, shade));
+        }
+    }
+
+    for mut visible in grid.iter_mut() {
+        visible.is_visible = theme.grid.is_some();
+    }
+}
+
+/// Colours each segment along the gradient of its snake, as the colours shift whenever it grows.
+/// Dead snakes are faded, as other snakes pass through them.
+fn colour_snakes(
+    theme: Res<Theme>,
+    game: Res<GameState>,
+    players: Query<(&Player, &SnakeShades, &SnakeSegments)>,
+    mut segments: Query<(
+        Option<&mut TextureAtlasSprite>,
+        Option<&mut Handle<ColorMaterial>>,
+    )>,
+) {
+    for (player, shades, entities) in players.iter() {
+        let length = entities.0.len();
+        let is_alive = game.snakes.get(player.0).is_none_or(Snake::is_alive);
+
+        for (index, &entity) in entities.0.iter().enumerate() {
+            match segments.get_mut(entity) {
+                Ok((Some(mut sprite), _)) => {
+                    let segment_colour = theme.segment_colour(player.0, index, length);
+
+                    sprite.color = match is_alive {
+                        true => colour(segment_colour),
+                        false => colour(theme.faded(segment_colour)),
+                    };
+                }
+                Ok((None, Some(mut material))) => {
+                    let shade = match is_alive {
+                        true => &shades.0[shade_index(index, length)],
+                        false => &shades.0[shade_index(index, length) + BODY_SHADES + 1],
+                    };
+
+                    if *material != *shade {
+                        *material = shade.clone();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn reload_theme(time: Res<Time>, mut reload: ResMut<ThemeReload>, mut theme: ResMut<Theme>) {
+    if !reload.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    match reload.watcher.poll() {
+        Some(Ok(new_theme)) => {
+            info!("Reloaded the theme");
+            *theme = new_theme;
+        }
+        Some(Err(error)) => warn!("Failed to reload the theme: {}", error),
+        None => {}
+    }
+}
+
+/// Gives the players spawned by `setup_game` their keys, colours and segments. Players left without
+/// any keys are handed over to a bot.
+fn setup_players(
+    mut commands: Commands,
+    config: Res<SnakeConfig>,
+    theme: Res<Theme>,
+    rules: Res<GameRules>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    players: Query<(Entity, &Player, Option<&InputQueue>)>,
+) {
+    for (entity, player, queue) in players.iter() {
+        let shades = (0..2 * (BODY_SHADES + 1))
+            .map(|shade| {
+                let material = ColorMaterial::color(colour(shade_colour(&theme, player.0, shade)));
+                materials.add(material)
+            })
+            .collect();
+
+        commands
+            .entity(entity)
+            .insert(SnakeShades(shades))
+            .insert(SnakeSegments(Vec::new()));
+
+        match (queue, INPUT_BINDINGS.get(player.0)) {
+            (None, _) => {}
+            (Some(_), Some(&keys)) => {
+                commands.entity(entity).insert(InputBinding(keys));
+            }
+            (Some(_), None) => {
+                commands
+                    .entity(entity)
+                    .remove::<InputQueue>()
+                    .insert(Bot(config.bot.controller(&rules)));
+            }
+        }
+    }
 }
 
 #[derive(SystemLabel, Debug, Hash, PartialEq, Eq, Clone)]
-enum SnakeAction {
-    Eat,
+pub(crate) enum SnakeAction {
     Move,
+    Score,
+    GameOver,
+    Transform,
 }
 
-pub struct SnakeActionPlugin;
+/// Runs the game rules only, without reading the keyboard or touching any window, so it works under
+/// `MinimalPlugins`. Unless an `AppState` was added before this plugin, the game starts right away
+/// and restarts as soon as it ends.
+pub struct HeadlessSnakePlugin;
 
-impl Plugin for SnakeActionPlugin {
+impl Plugin for HeadlessSnakePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.insert_resource(SnakeSegments(Vec::new()))
-            .insert_resource(LastTailPosition(None))
-            .add_startup_system(setup.system())
-            .add_startup_stage("game_setup", SystemStage::single(spawn_snake.system()))
-            .add_system(update_latest_state.system())
-            .add_system(
-                move_snake
-                    .system()
-                    .label(SnakeAction::Move)
-                    .with_run_criteria(FixedTimestep::step(0.15)),
-            )
-            .add_system(
-                snake_eat
+        // A `GameRng`, `SnakeConfig` or `GameRules` inserted before this plugin is kept, otherwise
+        // defaults are used, with the rules coming from the config.
+        let config = app
+            .world()
+            .get_resource::<SnakeConfig>()
+            .cloned()
+            .unwrap_or_default();
+        let rules = match app.world().get_resource::<GameRules>() {
+            Some(rules) => rules.clone(),
+            None => config
+                .rules()
+                .unwrap_or_else(|error| panic!("Failed to load the level: {}", error)),
+        };
+
+        if app.world().get_resource::<State<AppState>>().is_none() {
+            app.add_state(AppState::Playing).add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(restart.system()),
+            );
+        }
+
+        app.init_resource::<GameRng>()
+            .init_resource::<Score>()
+            .insert_resource(config.clone())
+            .insert_resource(rules)
+            .insert_resource(TickTimer(Timer::from_seconds(
+                config.tick_interval as f32,
+                true,
+            )))
+            .insert_resource(TickInterval {
+                base: config.tick_interval,
+                current: config.tick_interval,
+            })
+            .add_event::<GrowthEvent>()
+            .add_event::<FoodEvent>()
+            .add_event::<GameOverEvent>()
+            .add_startup_system(setup_game.system())
+            .add_system_set(SystemSet::on_enter(AppState::Playing).with_system(start_game.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(update_tick_interval.system().before(SnakeAction::Move))
+                    .with_system(move_snake.system().label(SnakeAction::Move))
+                    .with_system(
+                        update_score
+                            .system()
+                            .label(SnakeAction::Score)
+                            .after(SnakeAction::Move),
+                    )
+                    .with_system(
+                        game_over
+                            .system()
+                            .label(SnakeAction::GameOver)
+                            .after(SnakeAction::Score),
+                    ),
+            );
+
+        if app.world().get_resource::<Broadcast>().is_some() {
+            app.add_system(
+                broadcast::broadcast_game
                     .system()
-                    .label(SnakeAction::Eat)
-                    .after(SnakeAction::Move),
-            )
-            .add_system(grow_snake.system().after(SnakeAction::Eat))
-            .add_system(spawn_food.system().after(SnakeAction::Eat))
-            .add_system(game_over.system())
-            .add_system_set_to_stage(
-                CoreStage::PostUpdate,
-                SystemSet::new()
-                    .with_system(update_transform_position.system())
-                    .with_system(update_size.system()),
+                    .after(SnakeAction::GameOver),
             );
+        }
+    }
+}
+
+pub struct SnakeActionPlugin;
+
+impl Plugin for SnakeActionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // When playing on a server, the game comes from its snapshots instead of being simulated.
+        // It is already under way there, and our snake joins it at once, so there is no menu.
+        if app.world().get_resource::<Connection>().is_some() {
+            app.add_state(AppState::Playing)
+                .add_plugin(client::ClientPlugin);
+        } else {
+            app.add_state(AppState::Menu)
+                .add_plugin(HeadlessSnakePlugin);
+        }
+
+        let config = app.world().get_resource::<SnakeConfig>().unwrap().clone();
+
+        // A `Theme` inserted before this plugin is kept, otherwise the config names the theme.
+        let theme = match app.world().get_resource::<Theme>() {
+            Some(theme) => theme.clone(),
+            None => config
+                .theme()
+                .unwrap_or_else(|error| panic!("Failed to load the theme: {}", error)),
+        };
+
+        if let Some(watcher) = config.theme_watcher() {
+            app.insert_resource(ThemeReload {
+                watcher,
+                timer: Timer::from_seconds(0.5, true),
+            })
+            .add_system(reload_theme.system());
+        }
+
+        app.insert_resource(ClearColor(colour(theme.background)))
+            .insert_resource(theme);
+
+        app.insert_resource(WindowDescriptor {
+            title: String::from("Snake!"),
+            width: config.window_width,
+            height: config.window_height,
+            ..Default::default()
+        })
+        .add_plugin(ui::UiPlugin)
+        .add_startup_system(setup.system())
+        .add_startup_system(sprites::setup_sprites.system())
+        .add_startup_system_to_stage(StartupStage::PostStartup, setup_players.system())
+        .add_system_set(
+            SystemSet::on_update(AppState::Playing)
+                .with_system(queue_input.system().before(SnakeAction::Move)),
+        )
+        .add_system(sync_snakes.system().after(SnakeAction::GameOver))
+        .add_system(sync_food.system().after(SnakeAction::GameOver))
+        .add_system(apply_theme.system())
+        .add_system_set_to_stage(
+            CoreStage::PostUpdate,
+            SystemSet::new()
+                .with_system(
+                    update_transform_position
+                        .system()
+                        .label(SnakeAction::Transform),
+                )
+                .with_system(update_size.system())
+                .with_system(sprites::update_snake_sprites.system())
+                .with_system(colour_snakes.system()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn only_changes_of_direction_are_recorded() {
+        let mut recorder =
+            ReplayRecorder::new(PathBuf::new(), Replay::new(0, GameRules::default()));
+
+        for _ in 0..10 {
+            recorder.record(&[SnakeState::Right, SnakeState::Right]);
+        }
+
+        recorder.record(&[SnakeState::Up, SnakeState::Right]);
+        recorder.record(&[SnakeState::Up, SnakeState::Right]);
+        recorder.record(&[SnakeState::Up, SnakeState::Down]);
+
+        assert_eq!(
+            recorder.replay.inputs,
+            vec![(10, 0, SnakeState::Up), (12, 1, SnakeState::Down)]
+        );
+    }
+
+    #[test]
+    fn recorded_games_play_back_the_same() {
+        let rules = GameRules {
+            players: 2,
+            boundary: BoundaryMode::Wrap,
+            ..Default::default()
+        };
+        let path =
+            std::env::temp_dir().join(format!("snake-{}-recorder.replay", std::process::id()));
+        let mut recorder = ReplayRecorder::new(path.clone(), Replay::new(7, rules.clone()));
+
+        let mut rng = GameRng::new(7);
+        let mut game = GameState::new(rules, &mut rng);
+        let mut recorded = Vec::new();
+
+        for tick in 0..200 {
+            let directions = [SnakeState::ALL[tick / 7 % 4], SnakeState::ALL[tick / 5 % 4]];
+            recorder.record(&directions);
+            game.step(&directions, &mut rng);
+            recorded.push((game.snakes.clone(), game.food));
+        }
+
+        recorder.replay.save(&path).unwrap();
+        let replay = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        let replay = replay.unwrap();
+
+        let mut rng = GameRng::new(replay.seed);
+        let mut game = GameState::new(replay.rules.clone(), &mut rng);
+        let mut player = ReplayPlayer::new(replay);
+
+        for (snakes, food) in recorded {
+            let mut directions = [SnakeState::Right; 2];
+            player.play(&mut directions);
+            game.step(&directions, &mut rng);
+
+            assert_eq!(game.snakes, snakes);
+            assert_eq!(game.food, food);
+        }
+    }
+
+    #[test]
+    fn headless_games_move_on_every_tick() {
+        let mut app = App::build();
+        app.insert_resource(SnakeConfig {
+            arena_width: 1000,
+            boundary: BoundaryMode::Wrap,
+            tick_interval: 0.01,
+            food_weights: [(String::from("normal"), 1)].iter().cloned().collect(),
+            ..Default::default()
+        })
+        .add_plugins(MinimalPlugins)
+        .add_plugin(HeadlessSnakePlugin);
+
+        app.app.update();
+        let start = app.world().get_resource::<GameState>().unwrap().snakes[0].segments[0];
+
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(15));
+            app.app.update();
+        }
+
+        let snake = &app.world().get_resource::<GameState>().unwrap().snakes[0];
+        assert!(snake.is_alive());
+        assert_eq!(snake.segments[0].1, start.1);
+        assert!(snake.segments[0].0 > start.0 + 2);
     }
 }
@@ -1,18 +1,86 @@
+use std::{path::PathBuf, time::Duration};
+
 use bevy::prelude::*;
+use snake::{
+    cli::{self, argument_value},
+    net::DEFAULT_PORT,
+    Connection, GameRng, Replay, ReplayPlayer, ReplayRecorder,
+};
 
 fn main() {
-    App::build()
-        .insert_resource(WindowDescriptor {
-            title: String::from("Snake!"),
-            width: 1000.0,
-            height: 1000.0,
-            ..Default::default()
-        })
-        .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
-        .add_event::<snake::GrowthEvent>()
-        .add_event::<snake::FoodEvent>()
-        .add_event::<snake::GameOverEvent>()
-        .add_plugin(snake::SnakeActionPlugin)
+    let config = cli::config();
+
+    let replay = argument_value("--replay").map(|path| {
+        Replay::load(&PathBuf::from(&path))
+            .unwrap_or_else(|error| panic!("Failed to load replay {}: {}", path, error))
+    });
+
+    let rules = match &replay {
+        Some(replay) => replay.rules.clone(),
+        None => config
+            .rules()
+            .unwrap_or_else(|error| panic!("Failed to load the level: {}", error)),
+    };
+
+    let rng = match &replay {
+        Some(replay) => GameRng::new(replay.seed),
+        None => cli::rng(),
+    };
+
+    let mut app = App::build();
+
+    if let Some(path) = argument_value("--record") {
+        app.insert_resource(ReplayRecorder::new(
+            PathBuf::from(path),
+            Replay::new(rng.seed(), rules.clone()),
+        ));
+    }
+
+    if let Some(replay) = replay {
+        app.insert_resource(ReplayPlayer::new(replay));
+    }
+
+    let joining = argument_value("--connect")
+        .map(|address| (address, false))
+        .or_else(|| argument_value("--watch").map(|address| (address, true)));
+
+    if let Some((mut address, watching)) = joining {
+        if !address.contains(':') {
+            address = format!("{}:{}", address, DEFAULT_PORT);
+        }
+
+        let mut connection = match watching {
+            true => Connection::watch(&address, &config.player_name),
+            false => Connection::connect(&address, &config.player_name),
+        }
+        .unwrap_or_else(|error| panic!("Failed to join {}: {}", address, error));
+
+        // Pretends the server is further away, to see how the game copes with a slow connection.
+        if let Some(lag) = argument_value("--lag") {
+            let milliseconds = lag
+                .parse()
+                .unwrap_or_else(|_| panic!("--lag takes milliseconds, not `{}`", lag));
+            connection.set_delay(Duration::from_millis(milliseconds));
+        }
+
+        app.insert_resource(connection);
+    } else if let Some(broadcast) = cli::broadcast() {
+        app.insert_resource(broadcast);
+    }
+
+    app.insert_resource(rng)
+        .insert_resource(config)
+        .insert_resource(rules);
+
+    if cli::has_flag("--headless") {
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(snake::HeadlessSnakePlugin)
+            .run();
+
+        return;
+    }
+
+    app.add_plugin(snake::SnakeActionPlugin)
         .add_plugins(DefaultPlugins)
         .run();
 }
@@ -0,0 +1,90 @@
+//! How values are spelled in replays, levels, themes and the network protocol. Enums take the names
+//! their serde derives give them in the config, so every format spells them the same way.
+
+use std::io::{self, ErrorKind};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::game::SpeedCurve;
+
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.into())
+}
+
+/// The name of a variant of an enum without any fields, like `speed_up` for `FoodKind::SpeedUp`.
+pub(crate) fn name<T: Serialize>(value: T) -> String {
+    toml::Value::try_from(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_owned))
+        .expect("Only variants without fields have names")
+}
+
+/// The variant of an enum called `name`, with `what` saying what the enum is in the error.
+pub(crate) fn parse_name<T: DeserializeOwned>(what: &str, name: &str) -> io::Result<T> {
+    toml::Value::String(name.to_owned())
+        .try_into()
+        .map_err(|_| invalid(format!("unknown {} `{}`", what, name)))
+}
+
+pub(crate) fn parse_number<T: std::str::FromStr>(text: Option<&str>) -> io::Result<T> {
+    text.and_then(|text| text.parse().ok())
+        .ok_or_else(|| invalid("expected a number"))
+}
+
+/// A speed curve as the words `shape measure start rate step floor`.
+pub(crate) fn speed_curve_words(curve: &SpeedCurve) -> String {
+    format!(
+        "{} {} {} {} {} {}",
+        name(curve.shape),
+        name(curve.measure),
+        curve.start,
+        curve.rate,
+        curve.step,
+        curve.floor
+    )
+}
+
+pub(crate) fn parse_speed_curve<'a>(
+    words: &mut impl Iterator<Item = &'a str>,
+) -> io::Result<SpeedCurve> {
+    Ok(SpeedCurve {
+        shape: parse_name("speed curve", words.next().unwrap_or_default())?,
+        measure: parse_name("speed measure", words.next().unwrap_or_default())?,
+        start: parse_number(words.next())?,
+        rate: parse_number(words.next())?,
+        step: parse_number(words.next())?,
+        floor: parse_number(words.next())?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{BoundaryMode, DeathCause, FoodKind, SnakeState};
+
+    #[test]
+    fn names_round_trip() {
+        for &kind in &FoodKind::ALL {
+            assert_eq!(parse_name::<FoodKind>("food", &name(kind)).unwrap(), kind);
+        }
+
+        for &direction in &SnakeState::ALL {
+            assert_eq!(
+                parse_name::<SnakeState>("direction", &name(direction)).unwrap(),
+                direction
+            );
+        }
+
+        assert_eq!(name(FoodKind::SpeedUp), "speed_up");
+        assert_eq!(name(BoundaryMode::Wrap), "wrap");
+        assert_eq!(name(DeathCause::OtherSnake), "other_snake");
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        let error = parse_name::<FoodKind>("food", "pizza").unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unknown food `pizza`");
+    }
+}
@@ -0,0 +1,638 @@
+//! The protocol spoken between `snake-server` and the game's client mode, over TCP with one message
+//! per line. Every connection starts with the client's `hello` and the server's `welcome` or
+//! `refused`, after which the server sends a snapshot of the game on every tick. Turns are numbered
+//! by the client, so that each snapshot can tell it which of its turns the game already includes.
+//! Spectators say `watch` instead of `hello`, and are welcomed without a snake of their own.
+
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    game::{Arena, DeathCause, Food, GameRules, GameState, Position, Snake, SnakeState},
+    names::{invalid, name, parse_name, parse_number, parse_speed_curve, speed_curve_words},
+};
+
+/// Bumped on every change to the messages, so that mismatched clients and servers refuse each
+/// other instead of misreading each other.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+/// How far a connection may fall behind before it is given up on.
+const BUFFER_LIMIT: usize = 1 << 20;
+
+/// Everything a client needs to draw the game as it is on the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u64,
+    /// The number of the last turn from the receiving client that the server has dealt with,
+    /// either by applying it or by dropping it.
+    pub ack: u64,
+    pub food: Option<Food>,
+    pub speed: Option<(u32, u32)>,
+    pub snakes: Vec<Snake>,
+}
+
+impl Snapshot {
+    pub fn new(tick: u64, ack: u64, game: &GameState) -> Self {
+        Snapshot {
+            tick,
+            ack,
+            food: game.food,
+            speed: game.speed,
+            snakes: game.snakes.clone(),
+        }
+    }
+
+    /// Replaces the snakes, the food and the speed of `game` with those in the snapshot.
+    pub fn apply(&self, game: &mut GameState) {
+        game.food = self.food;
+        game.speed = self.speed;
+        game.snakes = self.snakes.clone();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Hello {
+        version: u32,
+        name: String,
+    },
+    /// Like `Hello`, but only to watch the game.
+    Watch {
+        version: u32,
+        name: String,
+    },
+    Turn {
+        sequence: u64,
+        direction: SnakeState,
+    },
+    /// Asks for a `Pong` with the same number, to measure the round trip time.
+    Ping(u64),
+    Bye,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    /// Accepts a client as the given player, or as a spectator without one, along with the rules of
+    /// the game.
+    Welcome {
+        version: u32,
+        player: Option<usize>,
+        /// Seconds between two ticks of the server.
+        tick_interval: f64,
+        rules: GameRules,
+    },
+    Refused(String),
+    Joined {
+        player: usize,
+        name: String,
+    },
+    Left {
+        player: usize,
+    },
+    Snapshot(Snapshot),
+    Pong(u64),
+}
+
+fn position_name(position: Position) -> String {
+    format!("{},{}", position.0, position.1)
+}
+
+fn parse_position(text: &str) -> io::Result<Position> {
+    let (x, y) = text
+        .split_once(',')
+        .ok_or_else(|| invalid(format!("expected a position, got `{}`", text)))?;
+
+    Ok(Position(parse_number(Some(x))?, parse_number(Some(y))?))
+}
+
+fn death_name(death: Option<DeathCause>) -> String {
+    death.map_or_else(|| String::from("alive"), name)
+}
+
+fn parse_death(death: &str) -> io::Result<Option<DeathCause>> {
+    match death {
+        "alive" => Ok(None),
+        _ => parse_name("death", death).map(Some),
+    }
+}
+
+/// Names are sent as the rest of a line, so they can't hold line breaks of their own.
+fn single_line(name: &str) -> String {
+    name.replace(['\n', '\r'], " ")
+}
+
+/// The rest of a line after its first `count` words.
+fn rest_after(line: &str, count: usize) -> String {
+    line.splitn(count + 1, ' ')
+        .nth(count)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            ClientMessage::Hello { version, name } => {
+                format!("hello {} {}", version, single_line(name))
+            }
+            ClientMessage::Watch { version, name } => {
+                format!("watch {} {}", version, single_line(name))
+            }
+            ClientMessage::Turn {
+                sequence,
+                direction,
+            } => format!("turn {} {}", sequence, name(*direction)),
+            ClientMessage::Ping(id) => format!("ping {}", id),
+            ClientMessage::Bye => String::from("bye"),
+        }
+    }
+
+    pub fn decode(line: &str) -> io::Result<Self> {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("hello") => Ok(ClientMessage::Hello {
+                version: parse_number(words.next())?,
+                name: rest_after(line, 2),
+            }),
+            Some("watch") => Ok(ClientMessage::Watch {
+                version: parse_number(words.next())?,
+                name: rest_after(line, 2),
+            }),
+            Some("turn") => Ok(ClientMessage::Turn {
+                sequence: parse_number(words.next())?,
+                direction: parse_name("direction", words.next().unwrap_or_default())?,
+            }),
+            Some("ping") => Ok(ClientMessage::Ping(parse_number(words.next())?)),
+            Some("bye") => Ok(ClientMessage::Bye),
+            _ => Err(invalid(format!("unknown message `{}`", line))),
+        }
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            ServerMessage::Welcome {
+                version,
+                player,
+                tick_interval,
+                rules,
+            } => {
+                let mut line = format!(
+                    "welcome {} {} {} {} {} {} {} {} {} {}",
+                    version,
+                    player.map_or_else(|| String::from("-"), |player| player.to_string()),
+                    tick_interval,
+                    rules.arena.0,
+                    rules.arena.1,
+                    name(rules.boundary),
+                    rules.players,
+                    rules.initial_length,
+                    name(rules.starting_direction),
+                    speed_curve_words(&rules.speed)
+                );
+
+                for &wall in &rules.walls {
+                    line.push(' ');
+                    line.push_str(&position_name(wall));
+                }
+
+                line
+            }
+            ServerMessage::Refused(reason) => format!("refused {}", single_line(reason)),
+            ServerMessage::Joined { player, name } => {
+                format!("joined {} {}", player, single_line(name))
+            }
+            ServerMessage::Left { player } => format!("left {}", player),
+            ServerMessage::Snapshot(snapshot) => {
+                let mut line = format!("snapshot {} {}", snapshot.tick, snapshot.ack);
+
+                match snapshot.speed {
+                    Some((percent, ticks)) => line.push_str(&format!(" {} {}", percent, ticks)),
+                    None => line.push_str(" -"),
+                }
+
+                match snapshot.food {
+                    Some(food) => line.push_str(&format!(
+                        " {} {} {}",
+                        name(food.kind),
+                        position_name(food.position),
+                        food.ticks_left
+                            .map_or(String::from("-"), |ticks| ticks.to_string())
+                    )),
+                    None => line.push_str(" -"),
+                }
+
+                // Snakes are separated by semicolons, as they have any number of segments.
+                for snake in &snapshot.snakes {
+                    line.push_str(&format!(
+                        " ; {} {} {} {} {}",
+                        death_name(snake.death),
+                        name(snake.heading),
+                        snake.score,
+                        snake.growth,
+                        snake.ghost
+                    ));
+
+                    for &segment in &snake.segments {
+                        line.push(' ');
+                        line.push_str(&position_name(segment));
+                    }
+                }
+
+                line
+            }
+            ServerMessage::Pong(id) => format!("pong {}", id),
+        }
+    }
+
+    pub fn decode(line: &str) -> io::Result<Self> {
+        let mut words = line.split_whitespace();
+
+        match words.next() {
+            Some("welcome") => {
+                // The version comes first, so that it can be checked even if the rest changed.
+                let version = parse_number(words.next())?;
+                let player = match words.next() {
+                    Some("-") => None,
+                    word => Some(parse_number(word)?),
+                };
+                let tick_interval = parse_number(words.next())?;
+                let rules = GameRules {
+                    arena: Arena(parse_number(words.next())?, parse_number(words.next())?),
+                    boundary: parse_name("boundary mode", words.next().unwrap_or_default())?,
+                    players: parse_number(words.next())?,
+                    initial_length: parse_number(words.next())?,
+                    starting_direction: parse_name("direction", words.next().unwrap_or_default())?,
+                    speed: parse_speed_curve(&mut words)?,
+                    walls: words.map(parse_position).collect::<io::Result<_>>()?,
+                    ..Default::default()
+                };
+
+                Ok(ServerMessage::Welcome {
+                    version,
+                    player,
+                    tick_interval,
+                    rules,
+                })
+            }
+            Some("refused") => Ok(ServerMessage::Refused(rest_after(line, 1))),
+            Some("joined") => Ok(ServerMessage::Joined {
+                player: parse_number(words.next())?,
+                name: rest_after(line, 2),
+            }),
+            Some("left") => Ok(ServerMessage::Left {
+                player: parse_number(words.next())?,
+            }),
+            Some("snapshot") => {
+                let mut parts = line.split(';');
+                let mut header = parts.next().unwrap_or_default().split_whitespace().skip(1);
+                let tick = parse_number(header.next())?;
+                let ack = parse_number(header.next())?;
+                let speed = match header.next() {
+                    Some("-") => None,
+                    percent => Some((parse_number(percent)?, parse_number(header.next())?)),
+                };
+                let food = match header.next() {
+                    Some("-") => None,
+                    kind => Some(Food {
+                        kind: parse_name("food", kind.unwrap_or_default())?,
+                        position: parse_position(header.next().unwrap_or_default())?,
+                        ticks_left: match header.next() {
+                            Some("-") => None,
+                            ticks => Some(parse_number(ticks)?),
+                        },
+                    }),
+                };
+
+                let snakes = parts
+                    .map(|part| {
+                        let mut words = part.split_whitespace();
+
+                        Ok(Snake {
+                            death: parse_death(words.next().unwrap_or_default())?,
+                            heading: parse_name("direction", words.next().unwrap_or_default())?,
+                            score: parse_number(words.next())?,
+                            growth: parse_number(words.next())?,
+                            ghost: parse_number(words.next())?,
+                            segments: words.map(parse_position).collect::<io::Result<_>>()?,
+                        })
+                    })
+                    .collect::<io::Result<Vec<Snake>>>()?;
+
+                Ok(ServerMessage::Snapshot(Snapshot {
+                    tick,
+                    ack,
+                    food,
+                    speed,
+                    snakes,
+                }))
+            }
+            Some("pong") => Ok(ServerMessage::Pong(parse_number(words.next())?)),
+            _ => Err(invalid(format!("unknown message `{}`", line))),
+        }
+    }
+}
+
+/// A TCP connection carrying one message per line, which never blocks. Incoming lines are held
+/// back until they are complete, and outgoing ones until the socket takes them.
+pub struct MessageStream {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+    delay: Duration,
+    /// Lines held back by the delay, with when they are due, in both directions.
+    delayed_incoming: VecDeque<(Instant, String)>,
+    delayed_outgoing: VecDeque<(Instant, String)>,
+}
+
+impl MessageStream {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+
+        Ok(MessageStream {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+            closed: false,
+            delay: Duration::ZERO,
+            delayed_incoming: VecDeque::new(),
+            delayed_outgoing: VecDeque::new(),
+        })
+    }
+
+    /// Holds back every line sent and received by `delay`, to try out a slow connection over
+    /// localhost.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// Queues a line to be written by the next `flush`.
+    pub fn send(&mut self, line: &str) {
+        if self.delay.is_zero() {
+            self.outgoing.extend_from_slice(line.as_bytes());
+            self.outgoing.push(b'\n');
+        } else {
+            self.delayed_outgoing
+                .push_back((Instant::now() + self.delay, line.to_owned()));
+        }
+    }
+
+    /// Writes as much of what was sent as the socket takes without blocking.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+
+        while let Some((due, _)) = self.delayed_outgoing.front() {
+            if *due > now {
+                break;
+            }
+
+            let (_, line) = self.delayed_outgoing.pop_front().unwrap();
+            self.outgoing.extend_from_slice(line.as_bytes());
+            self.outgoing.push(b'\n');
+        }
+
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        if self.outgoing.len() > BUFFER_LIMIT {
+            return Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "the other side stopped reading",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Every complete line received since the last call. Fails once the other side has hung up
+    /// and every line it sent before that was returned.
+    pub fn receive(&mut self) -> io::Result<Vec<String>> {
+        let mut buffer = [0; 4096];
+
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let mut lines = Vec::new();
+
+        while let Some(end) = self.incoming.iter().position(|&byte| byte == b'\n') {
+            let line = self.incoming.drain(..=end).collect::<Vec<u8>>();
+            lines.push(String::from_utf8_lossy(&line).trim_end().to_owned());
+        }
+
+        if self.incoming.len() > BUFFER_LIMIT {
+            return Err(invalid("line too long"));
+        }
+
+        if !self.delay.is_zero() {
+            let now = Instant::now();
+            let due = now + self.delay;
+
+            self.delayed_incoming
+                .extend(lines.drain(..).map(|line| (due, line)));
+
+            while let Some((due, _)) = self.delayed_incoming.front() {
+                if *due > now {
+                    break;
+                }
+
+                lines.push(self.delayed_incoming.pop_front().unwrap().1);
+            }
+        }
+
+        if self.closed && lines.is_empty() && self.delayed_incoming.is_empty() {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "the connection was closed",
+            ));
+        }
+
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{BoundaryMode, CurveShape, FoodKind, SpeedCurve, SpeedMeasure};
+
+    fn snake(segments: &[(i32, i32)], death: Option<DeathCause>) -> Snake {
+        Snake {
+            segments: segments.iter().map(|&(x, y)| Position(x, y)).collect(),
+            heading: SnakeState::Down,
+            score: 12,
+            growth: 2,
+            ghost: 5,
+            death,
+        }
+    }
+
+    fn server_round_trip(message: ServerMessage) {
+        assert_eq!(ServerMessage::decode(&message.encode()).unwrap(), message);
+    }
+
+    fn client_round_trip(message: ClientMessage) {
+        assert_eq!(ClientMessage::decode(&message.encode()).unwrap(), message);
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        client_round_trip(ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name: String::from("Ada Lovelace"),
+        });
+        client_round_trip(ClientMessage::Watch {
+            version: PROTOCOL_VERSION,
+            name: String::from("a  spaced   out name"),
+        });
+        client_round_trip(ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name: String::new(),
+        });
+        client_round_trip(ClientMessage::Turn {
+            sequence: 42,
+            direction: SnakeState::Up,
+        });
+        client_round_trip(ClientMessage::Ping(7));
+        client_round_trip(ClientMessage::Bye);
+    }
+
+    #[test]
+    fn names_are_kept_to_one_line() {
+        let message = ClientMessage::Hello {
+            version: 1,
+            name: String::from("two\nlines"),
+        };
+
+        assert_eq!(
+            ClientMessage::decode(&message.encode()).unwrap(),
+            ClientMessage::Hello {
+                version: 1,
+                name: String::from("two lines"),
+            }
+        );
+    }
+
+    #[test]
+    fn welcome_round_trips() {
+        server_round_trip(ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player: Some(1),
+            tick_interval: 0.15,
+            rules: GameRules {
+                arena: Arena(20, 12),
+                boundary: BoundaryMode::Wrap,
+                walls: vec![Position(0, 0), Position(3, 4), Position(19, 11)],
+                players: 3,
+                initial_length: 4,
+                starting_direction: SnakeState::Left,
+                speed: SpeedCurve {
+                    shape: CurveShape::Exponential,
+                    measure: SpeedMeasure::Length,
+                    start: 90,
+                    rate: 5,
+                    step: 3,
+                    floor: 40,
+                },
+                ..Default::default()
+            },
+        });
+        server_round_trip(ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player: None,
+            tick_interval: 0.1,
+            rules: GameRules::default(),
+        });
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        server_round_trip(ServerMessage::Snapshot(Snapshot {
+            tick: 300,
+            ack: 17,
+            food: Some(Food {
+                position: Position(4, 9),
+                kind: FoodKind::SpeedUp,
+                ticks_left: Some(25),
+            }),
+            speed: Some((60, 12)),
+            snakes: vec![
+                snake(&[(5, 5), (5, 6), (6, 6)], None),
+                snake(&[(1, 1), (1, 2)], Some(DeathCause::HeadOn)),
+            ],
+        }));
+        server_round_trip(ServerMessage::Snapshot(Snapshot {
+            tick: 0,
+            ack: 0,
+            food: Some(Food {
+                position: Position(0, 0),
+                kind: FoodKind::Normal,
+                ticks_left: None,
+            }),
+            speed: None,
+            snakes: vec![snake(&[(2, 3)], None)],
+        }));
+        server_round_trip(ServerMessage::Snapshot(Snapshot {
+            tick: 9,
+            ack: 3,
+            food: None,
+            speed: None,
+            snakes: vec![
+                snake(&[], Some(DeathCause::Left)),
+                snake(&[(7, 7), (8, 7)], Some(DeathCause::OwnBody)),
+                snake(&[], Some(DeathCause::Wall)),
+            ],
+        }));
+        server_round_trip(ServerMessage::Snapshot(Snapshot {
+            tick: 1,
+            ack: 1,
+            food: None,
+            speed: None,
+            snakes: Vec::new(),
+        }));
+    }
+
+    #[test]
+    fn other_server_messages_round_trip() {
+        server_round_trip(ServerMessage::Refused(String::from("the game is full")));
+        server_round_trip(ServerMessage::Joined {
+            player: 2,
+            name: String::from("Grace Hopper"),
+        });
+        server_round_trip(ServerMessage::Left { player: 0 });
+        server_round_trip(ServerMessage::Pong(99));
+    }
+
+    #[test]
+    fn unknown_messages_are_rejected() {
+        assert!(ClientMessage::decode("dance 3").is_err());
+        assert!(ClientMessage::decode("turn 3 sideways").is_err());
+        assert!(ServerMessage::decode("").is_err());
+        assert!(ServerMessage::decode("snapshot 1 1 - - ; dizzy up 0 0 0").is_err());
+    }
+}
@@ -0,0 +1,326 @@
+//! The `snake` Python module, built by running `maturin develop` from the repository root, which
+//! turns on the `python` feature.
+//!
+//! Actions are indices into `SnakeState::ALL`, that is 0 for left, 1 for right, 2 for down and 3
+//! for up.
+
+use std::path::Path;
+
+use numpy::{IntoPyArray, PyArray1, PyArrayDyn, PyReadonlyArray1};
+use pyo3::{
+    exceptions::{PyIOError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{
+    names, BatchEnv, EnvConfig, GameState, Observation, ObservationKind, Position, RewardShaping,
+    SnakeConfig, SnakeEnv, SnakeState, StepInfo, StepOutcome, Theme,
+};
+
+/// Builds the environment config shared by `SnakeEnv` and `BatchEnv`. The rules and opponents come
+/// from a config file, the same one the game reads.
+fn env_config(
+    config: Option<&str>,
+    observation: &str,
+    max_steps: Option<u64>,
+    rewards: Option<&PyDict>,
+) -> PyResult<(SnakeConfig, EnvConfig)> {
+    let config = match config {
+        Some(path) => SnakeConfig::load(Path::new(path))
+            .map_err(|error| PyIOError::new_err(error.to_string()))?,
+        None => SnakeConfig::default(),
+    };
+
+    let observation = match observation {
+        "grid" => ObservationKind::Grid,
+        "rays" => ObservationKind::Rays,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown observation `{}`, expected `grid` or `rays`",
+                observation
+            )))
+        }
+    };
+
+    let mut shaping = RewardShaping::default();
+
+    if let Some(rewards) = rewards {
+        for (key, value) in rewards.iter() {
+            let value = value.extract::<f32>()?;
+
+            match key.extract::<&str>()? {
+                "food" => shaping.food = value,
+                "death" => shaping.death = value,
+                "step" => shaping.step = value,
+                "approach" => shaping.approach = value,
+                key => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown reward `{}`, expected `food`, `death`, `step` or `approach`",
+                        key
+                    )))
+                }
+            }
+        }
+    }
+
+    let env_config = EnvConfig {
+        rules: config
+            .rules()
+            .map_err(|error| PyIOError::new_err(error.to_string()))?,
+        observation,
+        rewards: shaping,
+        max_steps,
+        opponent: config.bot,
+    };
+
+    Ok((config, env_config))
+}
+
+fn action(index: usize) -> PyResult<SnakeState> {
+    SnakeState::ALL
+        .get(index)
+        .copied()
+        .ok_or_else(|| PyValueError::new_err(format!("invalid action {}, expected 0 to 3", index)))
+}
+
+fn observation_array(py: Python<'_>, observation: Observation) -> PyResult<&PyArrayDyn<f32>> {
+    observation.data.into_pyarray(py).reshape(observation.shape)
+}
+
+fn info_dict(py: Python<'_>, info: StepInfo) -> PyResult<&PyDict> {
+    let dict = PyDict::new(py);
+
+    dict.set_item("score", info.score)?;
+    dict.set_item("length", info.length)?;
+    dict.set_item("steps", info.steps)?;
+    dict.set_item("truncated", info.truncated)?;
+    dict.set_item(
+        "death",
+        match info.outcome {
+            StepOutcome::Died(cause) => Some(names::name(cause)),
+            _ => None,
+        },
+    )?;
+
+    Ok(dict)
+}
+
+/// Draws the game as a `[height, width, 3]` RGB image with `scale` pixels per tile, the top row
+/// first, in the colours of the theme.
+fn render(game: &GameState, theme: &Theme, scale: usize) -> Vec<u8> {
+    let arena = game.rules.arena;
+    let (width, height) = (arena.0 as usize * scale, arena.1 as usize * scale);
+    let background = theme.background.map(|channel| (channel * 255.0) as u8);
+    let mut pixels = background.repeat(width * height);
+
+    let mut fill = |position: Position, [red, green, blue]: [f32; 3]| {
+        if !game.in_bounds(position) {
+            return;
+        }
+
+        let top = (arena.1 as usize - 1 - position.1 as usize) * scale;
+        let left = position.0 as usize * scale;
+
+        for y in top..top + scale {
+            for x in left..left + scale {
+                let pixel = (y * width + x) * 3;
+
+                pixels[pixel..pixel + 3].copy_from_slice(&[
+                    (red * 255.0) as u8,
+                    (green * 255.0) as u8,
+                    (blue * 255.0) as u8,
+                ]);
+            }
+        }
+    };
+
+    for &wall in &game.rules.walls {
+        fill(wall, theme.wall);
+    }
+
+    if let Some(food) = game.food {
+        fill(food.position, theme.food_colour(food.kind));
+    }
+
+    for (player, snake) in game.snakes.iter().enumerate() {
+        for (index, &segment) in snake.segments.iter().enumerate() {
+            let colour = theme.segment_colour(player, index, snake.segments.len());
+
+            match snake.is_alive() {
+                true => fill(segment, colour),
+                false => fill(segment, theme.faded(colour)),
+            }
+        }
+    }
+
+    pixels
+}
+
+#[pyclass(name = "SnakeEnv")]
+struct PySnakeEnv {
+    theme: Theme,
+    env: SnakeEnv,
+}
+
+#[pymethods]
+impl PySnakeEnv {
+    #[new]
+    #[args(
+        config = "None",
+        observation = "\"grid\"",
+        max_steps = "None",
+        rewards = "None"
+    )]
+    fn new(
+        config: Option<&str>,
+        observation: &str,
+        max_steps: Option<u64>,
+        rewards: Option<&PyDict>,
+    ) -> PyResult<Self> {
+        let (config, env_config) = env_config(config, observation, max_steps, rewards)?;
+
+        Ok(PySnakeEnv {
+            theme: config
+                .theme()
+                .map_err(|error| PyIOError::new_err(error.to_string()))?,
+            env: SnakeEnv::new(env_config),
+        })
+    }
+
+    #[getter]
+    fn observation_shape(&self) -> Vec<usize> {
+        self.env.observation_shape()
+    }
+
+    #[getter]
+    fn action_count(&self) -> usize {
+        SnakeState::ALL.len()
+    }
+
+    /// Starts a new episode, with a random seed unless one is given.
+    #[args(seed = "None")]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: Option<u64>) -> PyResult<&'py PyArrayDyn<f32>> {
+        observation_array(py, self.env.reset(seed.unwrap_or_else(rand::random)))
+    }
+
+    /// Returns the observation, the reward, whether the episode is done and a dict of extra info.
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action_index: usize,
+    ) -> PyResult<(&'py PyArrayDyn<f32>, f32, bool, &'py PyDict)> {
+        let (observation, reward, done, info) = self.env.step(action(action_index)?);
+
+        Ok((
+            observation_array(py, observation)?,
+            reward,
+            done,
+            info_dict(py, info)?,
+        ))
+    }
+
+    #[args(scale = "1")]
+    fn render<'py>(&self, py: Python<'py>, scale: usize) -> PyResult<&'py PyArrayDyn<u8>> {
+        let arena = self.env.game().rules.arena;
+        let scale = scale.max(1);
+
+        render(self.env.game(), &self.theme, scale)
+            .into_pyarray(py)
+            .reshape(vec![arena.1 as usize * scale, arena.0 as usize * scale, 3])
+    }
+}
+
+/// Many games stepped in parallel, returning `[count, ...]` arrays. Games reset themselves when
+/// they end.
+#[pyclass(name = "BatchEnv")]
+struct PyBatchEnv {
+    batch: BatchEnv,
+}
+
+impl PyBatchEnv {
+    fn observations<'py>(&self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f32>> {
+        let mut shape = vec![self.batch.len()];
+        shape.extend(self.batch.observation_shape());
+
+        PyArray1::from_slice(py, self.batch.observations()).reshape(shape)
+    }
+}
+
+#[pymethods]
+impl PyBatchEnv {
+    #[new]
+    #[args(
+        seed = "0",
+        config = "None",
+        observation = "\"grid\"",
+        max_steps = "None",
+        rewards = "None"
+    )]
+    fn new(
+        count: usize,
+        seed: u64,
+        config: Option<&str>,
+        observation: &str,
+        max_steps: Option<u64>,
+        rewards: Option<&PyDict>,
+    ) -> PyResult<Self> {
+        let (_, env_config) = env_config(config, observation, max_steps, rewards)?;
+
+        Ok(PyBatchEnv {
+            batch: BatchEnv::new(env_config, count, seed),
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.batch.len()
+    }
+
+    fn reset<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyArrayDyn<f32>> {
+        self.batch.reset();
+        self.observations(py)
+    }
+
+    /// Returns the observations, the rewards and the done flags of every game.
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        actions: PyReadonlyArray1<i64>,
+    ) -> PyResult<(
+        &'py PyArrayDyn<f32>,
+        &'py PyArray1<f32>,
+        &'py PyArray1<bool>,
+    )> {
+        let actions = actions
+            .as_array()
+            .iter()
+            .map(|&index| action(index as usize))
+            .collect::<PyResult<Vec<SnakeState>>>()?;
+
+        if actions.len() != self.batch.len() {
+            return Err(PyValueError::new_err(format!(
+                "expected {} actions, got {}",
+                self.batch.len(),
+                actions.len()
+            )));
+        }
+
+        // Stepping the games doesn't need Python, so other Python threads can run meanwhile.
+        let batch = &mut self.batch;
+        py.allow_threads(|| batch.step(&actions));
+
+        Ok((
+            self.observations(py)?,
+            PyArray1::from_slice(py, self.batch.rewards()),
+            PyArray1::from_slice(py, self.batch.dones()),
+        ))
+    }
+}
+
+#[pymodule]
+fn snake(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PySnakeEnv>()?;
+    module.add_class::<PyBatchEnv>()?;
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    game::{Arena, GameRules, Position, SnakeState},
+    names::{invalid, name, parse_name, parse_number, parse_speed_curve, speed_curve_words},
+};
+
+pub const REPLAY_VERSION: u32 = 4;
+
+/// Everything needed to reproduce a session: the seed and rules it started with, and the tick of
+/// every change to the direction fed into `GameState::step` for each player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub rules: GameRules,
+    /// The tick, the player and their new direction.
+    pub inputs: Vec<(u64, usize, SnakeState)>,
+}
+
+impl Replay {
+    /// The direction of a player before their first input.
+    pub const FIRST_DIRECTION: SnakeState = SnakeState::Right;
+
+    pub fn new(seed: u64, rules: GameRules) -> Self {
+        Replay {
+            seed,
+            rules,
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let rules = &self.rules;
+        let mut contents = format!(
+            "snake-replay {}\nseed {}\narena {} {}\nboundary {}\nplayers {}\nlength {}\ndirection {}\nspeed {}\n",
+            REPLAY_VERSION,
+            self.seed,
+            rules.arena.0,
+            rules.arena.1,
+            name(rules.boundary),
+            rules.players,
+            rules.initial_length,
+            name(rules.starting_direction),
+            speed_curve_words(&rules.speed)
+        );
+
+        for spawn in &rules.spawns {
+            contents.push_str(&format!("spawn {} {}\n", spawn.0, spawn.1));
+        }
+
+        for wall in &rules.walls {
+            contents.push_str(&format!("wall {} {}\n", wall.0, wall.1));
+        }
+
+        for &(kind, weight) in &rules.food_weights {
+            contents.push_str(&format!("food {} {}\n", name(kind), weight));
+        }
+
+        for &(tick, player, direction) in &self.inputs {
+            contents.push_str(&format!("{} {} {}\n", tick, player, name(direction)));
+        }
+
+        fs::write(path, contents)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines().map(|line| line.split_whitespace());
+
+        let mut header = lines.next().ok_or_else(|| invalid("empty replay file"))?;
+        if header.next() != Some("snake-replay") {
+            return Err(invalid("not a replay file"));
+        }
+
+        let version: u32 = parse_number(header.next())?;
+        if version == 0 || version > REPLAY_VERSION {
+            return Err(invalid(format!("unsupported replay version {}", version)));
+        }
+
+        // Rules missing from the file keep their defaults, which is what older recordings used.
+        let mut seed = None;
+        let mut rules = GameRules::default();
+        let mut inputs = Vec::new();
+        let mut food_weights = Vec::new();
+
+        for mut line in lines {
+            match line.next() {
+                None => {}
+                Some("seed") => seed = Some(parse_number(line.next())?),
+                Some("arena") => {
+                    rules.arena = Arena(parse_number(line.next())?, parse_number(line.next())?)
+                }
+                Some("boundary") => {
+                    rules.boundary = parse_name("boundary mode", line.next().unwrap_or_default())?
+                }
+                Some("spawn") => rules.spawns.push(Position(
+                    parse_number(line.next())?,
+                    parse_number(line.next())?,
+                )),
+                Some("players") => rules.players = parse_number(line.next())?,
+                Some("wall") => rules.walls.push(Position(
+                    parse_number(line.next())?,
+                    parse_number(line.next())?,
+                )),
+                Some("food") => food_weights.push((
+                    parse_name("food", line.next().unwrap_or_default())?,
+                    parse_number(line.next())?,
+                )),
+                Some("length") => rules.initial_length = parse_number(line.next())?,
+                Some("speed") => rules.speed = parse_speed_curve(&mut line)?,
+                Some("direction") => {
+                    rules.starting_direction =
+                        parse_name("direction", line.next().unwrap_or_default())?
+                }
+                Some(tick) => {
+                    let tick = parse_number(Some(tick))?;
+                    // Version 1 only had a single player, so its lines have no player number.
+                    let player = if version == 1 {
+                        0
+                    } else {
+                        parse_number(line.next())?
+                    };
+
+                    inputs.push((
+                        tick,
+                        player,
+                        parse_name("direction", line.next().unwrap_or_default())?,
+                    ))
+                }
+            }
+        }
+
+        if !food_weights.is_empty() {
+            rules.food_weights = food_weights;
+        }
+
+        Ok(Replay {
+            seed: seed.ok_or_else(|| invalid("missing seed"))?,
+            rules,
+            inputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::game::{BoundaryMode, CurveShape, FoodKind, SpeedCurve, SpeedMeasure};
+
+    /// A file in the temporary directory for a single test, so tests running at once don't clash.
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("snake-{}-{}.replay", std::process::id(), name))
+    }
+
+    fn load_str(name: &str, contents: &str) -> io::Result<Replay> {
+        let path = temp_file(name);
+        fs::write(&path, contents)?;
+        let replay = Replay::load(&path);
+        fs::remove_file(&path)?;
+        replay
+    }
+
+    #[test]
+    fn replays_round_trip() {
+        let mut replay = Replay::new(
+            1234,
+            GameRules {
+                arena: Arena(20, 10),
+                boundary: BoundaryMode::Wrap,
+                walls: vec![Position(0, 0), Position(5, 6)],
+                spawns: vec![Position(3, 3), Position(15, 7)],
+                players: 2,
+                initial_length: 5,
+                starting_direction: SnakeState::Up,
+                food_weights: vec![(FoodKind::Normal, 3), (FoodKind::Ghost, 1)],
+                speed: SpeedCurve {
+                    shape: CurveShape::Stepped,
+                    measure: SpeedMeasure::Length,
+                    start: 100,
+                    rate: 10,
+                    step: 5,
+                    floor: 30,
+                },
+            },
+        );
+
+        replay.inputs = vec![
+            (0, 1, SnakeState::Up),
+            (4, 0, SnakeState::Down),
+            (4, 1, SnakeState::Left),
+            (30, 0, SnakeState::Right),
+        ];
+
+        let path = temp_file("round-trip");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), replay);
+    }
+
+    #[test]
+    fn older_replays_keep_the_default_rules() {
+        let replay = load_str("version-1", "snake-replay 1\nseed 9\n\n3 up\n7 left\n").unwrap();
+
+        assert_eq!(replay.seed, 9);
+        assert_eq!(replay.rules, GameRules::default());
+        assert_eq!(
+            replay.inputs,
+            vec![(3, 0, SnakeState::Up), (7, 0, SnakeState::Left)]
+        );
+    }
+
+    #[test]
+    fn broken_replays_are_rejected() {
+        assert!(load_str("empty", "").is_err());
+        assert!(load_str("not-a-replay", "snake-level 1\nseed 1\n").is_err());
+        assert!(load_str("future", "snake-replay 99\nseed 1\n").is_err());
+        assert!(load_str("no-seed", "snake-replay 4\narena 10 10\n").is_err());
+        assert!(load_str("bad-input", "snake-replay 4\nseed 1\n3 0 sideways\n").is_err());
+    }
+}
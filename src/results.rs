@@ -0,0 +1,180 @@
+//! What the game over screens say, shared by the window and the terminal so that both tell the end
+//! of a game the same way.
+
+use crate::{
+    config::SnakeConfig,
+    game::{DeathCause, GameState},
+    high_score::{HighScore, HighScores},
+};
+
+/// The name a player goes by in the high score table and on other players' screens.
+pub fn player_name(config: &SnakeConfig, player: usize, is_bot: bool) -> String {
+    match (player, is_bot) {
+        (_, true) => format!("Bot {}", player + 1),
+        (0, false) => config.player_name.clone(),
+        (_, false) => format!("Player {}", player + 1),
+    }
+}
+
+/// How a finished game went for each player, in player order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameResults {
+    pub entries: Vec<HighScore>,
+    pub deaths: Vec<Option<DeathCause>>,
+    pub bots: Vec<bool>,
+}
+
+impl GameResults {
+    /// The results of `game`, with `scores` holding the points of each player.
+    pub fn new(
+        config: &SnakeConfig,
+        game: &GameState,
+        scores: &[u32],
+        is_bot: impl Fn(usize) -> bool,
+    ) -> Self {
+        let bots = (0..game.snakes.len()).map(is_bot).collect::<Vec<bool>>();
+        let entries = bots
+            .iter()
+            .enumerate()
+            .map(|(player, &is_bot)| {
+                HighScore::now(
+                    player_name(config, player, is_bot),
+                    scores.get(player).copied().unwrap_or(0),
+                    game.rules.arena.0,
+                    game.rules.arena.1,
+                    config.tick_interval,
+                )
+            })
+            .collect();
+
+        GameResults {
+            entries,
+            deaths: game.snakes.iter().map(|snake| snake.death).collect(),
+            bots,
+        }
+    }
+
+    /// Adds the scores of the people who played to the table.
+    pub fn record(&self, scores: &mut HighScores) {
+        // Bots play too well, and too often, to share the table with people.
+        for (entry, &is_bot) in self.entries.iter().zip(&self.bots) {
+            if !is_bot {
+                scores.insert(entry.clone());
+            }
+        }
+    }
+
+    /// How the game ended for every player, addressing the player directly when they played alone.
+    pub fn summary(&self) -> String {
+        if let ([entry], [death]) = (&self.entries[..], &self.deaths[..]) {
+            let cause = match death {
+                Some(DeathCause::Wall) => "You hit a wall",
+                Some(DeathCause::OwnBody) => "You ran into yourself",
+                Some(DeathCause::OtherSnake) => "You ran into another snake",
+                Some(DeathCause::HeadOn) => "You crashed head-on",
+                Some(DeathCause::Left) => "You left",
+                None => "Game over",
+            };
+
+            return format!("{}! You scored {}\n", cause, entry.score);
+        }
+
+        let mut contents = String::from("Game over!\n");
+
+        for (entry, death) in self.entries.iter().zip(&self.deaths) {
+            let cause = match death {
+                Some(DeathCause::Wall) => "hit a wall",
+                Some(DeathCause::OwnBody) => "ran into their own tail",
+                Some(DeathCause::OtherSnake) => "ran into another snake",
+                Some(DeathCause::HeadOn) => "crashed head-on",
+                Some(DeathCause::Left) => "left the game",
+                None => "survived",
+            };
+
+            contents.push_str(&format!(
+                "{} {} and scored {}\n",
+                entry.name, cause, entry.score
+            ));
+        }
+
+        contents
+    }
+
+    /// The high score table, with the entries from this game marked.
+    pub fn table(&self, scores: &HighScores) -> String {
+        let mut contents = String::from("\nHigh scores\n");
+
+        for (index, entry) in scores.entries.iter().enumerate() {
+            contents.push_str(&format!(
+                "{}{}. {}  {}  {}  {}x{}  {}s\n",
+                if self.entries.contains(entry) {
+                    "> "
+                } else {
+                    ""
+                },
+                index + 1,
+                entry.name,
+                entry.score,
+                entry.formatted_date(),
+                entry.arena_width,
+                entry.arena_height,
+                entry.tick_interval
+            ));
+        }
+
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::GameRules, rng::GameRng};
+
+    fn finished_game(deaths: &[Option<DeathCause>]) -> GameState {
+        let rules = GameRules {
+            players: deaths.len() as u32,
+            ..Default::default()
+        };
+        let mut game = GameState::new(rules, &mut GameRng::new(0));
+
+        for (snake, &death) in game.snakes.iter_mut().zip(deaths) {
+            snake.death = death;
+        }
+
+        game
+    }
+
+    #[test]
+    fn a_single_player_is_addressed_directly() {
+        let config = SnakeConfig::default();
+        let game = finished_game(&[Some(DeathCause::Wall)]);
+        let results = GameResults::new(&config, &game, &[3], |_| false);
+
+        assert_eq!(results.summary(), "You hit a wall! You scored 3\n");
+    }
+
+    #[test]
+    fn bots_are_named_and_left_out_of_the_table() {
+        let config = SnakeConfig {
+            player_name: String::from("Ada"),
+            ..Default::default()
+        };
+        let game = finished_game(&[Some(DeathCause::HeadOn), None]);
+        let results = GameResults::new(&config, &game, &[4, 9], |player| player == 1);
+
+        assert_eq!(
+            results.summary(),
+            "Game over!\nAda crashed head-on and scored 4\nBot 2 survived and scored 9\n"
+        );
+
+        let mut scores = HighScores::default();
+        results.record(&mut scores);
+
+        assert_eq!(scores.entries.len(), 1);
+        assert_eq!(scores.entries[0].name, "Ada");
+        assert!(results
+            .table(&scores)
+            .starts_with("\nHigh scores\n> 1. Ada  4  "));
+    }
+}
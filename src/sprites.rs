@@ -0,0 +1,180 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+
+use crate::{wrapped_offset, GameState, Position, SnakeHead, SnakeSegments, SnakeState, Theme};
+
+/// The tiles of a snake sprite sheet, in the order they appear along its single row. Every tile is
+/// drawn for a snake heading up: the head facing up, the body running from top to bottom, the
+/// corner joining the tiles below and to the right, and the tail with the body above it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tile {
+    Head = 0,
+    Body = 1,
+    Corner = 2,
+    Tail = 3,
+}
+
+/// The sprite sheet snakes are drawn with, present only when the configured sheet was found.
+pub(crate) struct SnakeSprites {
+    pub(crate) atlas: Handle<TextureAtlas>,
+    tile_size: f32,
+}
+
+/// Where the asset server looks for `path`, which is next to the manifest when run through cargo
+/// and next to the executable otherwise.
+fn asset_path(path: &Path) -> Option<PathBuf> {
+    let root = match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(directory) => PathBuf::from(directory),
+        None => env::current_exe().ok()?.parent()?.to_owned(),
+    };
+
+    Some(root.join("assets").join(path))
+}
+
+pub(crate) fn setup_sprites(
+    mut commands: Commands,
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let path = match &theme.snake_sheet {
+        Some(path) => path,
+        None => return,
+    };
+
+    // The asset server only logs a missing file, so it is looked for here to know whether to fall
+    // back to coloured squares.
+    if !asset_path(path).is_some_and(|full_path| full_path.exists()) {
+        info!(
+            "No snake sprite sheet at {}, drawing snakes as squares",
+            path.display()
+        );
+        return;
+    }
+
+    let tile_size = theme.snake_sheet_tile as f32;
+    let atlas = TextureAtlas::from_grid(
+        asset_server.load(path.as_path()),
+        Vec2::new(tile_size, tile_size),
+        4,
+        1,
+    );
+
+    commands.insert_resource(SnakeSprites {
+        atlas: atlases.add(atlas),
+        tile_size,
+    });
+}
+
+/// The direction from one tile to a neighbouring one, or `None` if they are not next to each other.
+fn direction(from: Position, to: Position, game: &GameState) -> Option<SnakeState> {
+    let arena = game.rules.arena;
+    let offset = Position(
+        wrapped_offset(from.0, to.0, arena.0, game.rules.boundary),
+        wrapped_offset(from.1, to.1, arena.1, game.rules.boundary),
+    );
+
+    SnakeState::ALL
+        .iter()
+        .copied()
+        .find(|direction| direction.offset() == offset)
+}
+
+/// How far a tile drawn for a snake heading up has to be turned anticlockwise to face `direction`.
+fn quarter_turns(direction: SnakeState) -> u32 {
+    match direction {
+        SnakeState::Up => 0,
+        SnakeState::Left => 1,
+        SnakeState::Down => 2,
+        SnakeState::Right => 3,
+    }
+}
+
+fn turn_anticlockwise(direction: SnakeState) -> SnakeState {
+    match direction {
+        SnakeState::Up => SnakeState::Left,
+        SnakeState::Left => SnakeState::Down,
+        SnakeState::Down => SnakeState::Right,
+        SnakeState::Right => SnakeState::Up,
+    }
+}
+
+/// The tile for a segment and its quarter turns, given the directions to the segments before and
+/// after it. Either is missing at the ends of the snake.
+fn pick_tile(towards_head: Option<SnakeState>, towards_tail: Option<SnakeState>) -> (Tile, u32) {
+    match (towards_head, towards_tail) {
+        (None, _) => (Tile::Head, 0),
+        (Some(towards_head), None) => (Tile::Tail, quarter_turns(towards_head)),
+        (Some(towards_head), Some(towards_tail)) if towards_head == towards_tail.opposite() => {
+            (Tile::Body, quarter_turns(towards_head) % 2)
+        }
+        (Some(towards_head), Some(towards_tail)) => {
+            // The corner tile joins down and right, so it is turned until it joins the same two.
+            let turns = (0..4)
+                .find(|&turns| {
+                    let joins = (0..turns).fold(
+                        (SnakeState::Down, SnakeState::Right),
+                        |(first, second), _| {
+                            (turn_anticlockwise(first), turn_anticlockwise(second))
+                        },
+                    );
+
+                    joins == (towards_head, towards_tail) || joins == (towards_tail, towards_head)
+                })
+                .unwrap_or(0);
+
+            (Tile::Corner, turns)
+        }
+    }
+}
+
+/// Picks the head, body, corner or tail tile for every segment of every snake, turning it to match
+/// its neighbours, and scales the tiles to the size of a tile of the arena.
+pub(crate) fn update_snake_sprites(
+    windows: Res<Windows>,
+    game: Res<GameState>,
+    sprites: Option<Res<SnakeSprites>>,
+    players: Query<&SnakeSegments>,
+    heads: Query<&SnakeHead>,
+    positions: Query<&Position>,
+    mut segments: Query<(&mut TextureAtlasSprite, &mut Transform)>,
+) {
+    let sprites = match sprites {
+        Some(sprites) => sprites,
+        None => return,
+    };
+
+    let window = windows.get_primary().unwrap();
+    let arena = game.rules.arena;
+    let scale = Vec3::new(
+        window.width() / arena.0 as f32 / sprites.tile_size,
+        window.height() / arena.1 as f32 / sprites.tile_size,
+        1.0,
+    );
+
+    for entities in players.iter() {
+        for (index, &entity) in entities.0.iter().enumerate() {
+            let neighbour = |other: Option<usize>| {
+                let position = positions.get(entity).ok()?;
+                let other = positions.get(*entities.0.get(other?)?).ok()?;
+                direction(*position, *other, &game)
+            };
+
+            let (tile, turns) = match heads.get(entity) {
+                Ok(head) => (Tile::Head, quarter_turns(head.0)),
+                Err(_) => pick_tile(neighbour(index.checked_sub(1)), neighbour(Some(index + 1))),
+            };
+
+            if let Ok((mut sprite, mut transform)) = segments.get_mut(entity) {
+                sprite.index = tile as u32;
+                transform.rotation =
+                    Quat::from_rotation_z(turns as f32 * std::f32::consts::FRAC_PI_2);
+                transform.scale = scale;
+            }
+        }
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game::FoodKind, names::name};
+
+/// The themes that ship with the game, besides `classic`, which is `Theme::default`.
+const BUILT_IN: [(&str, &str); 3] = [
+    ("neon", include_str!("../assets/themes/neon.toml")),
+    ("forest", include_str!("../assets/themes/forest.toml")),
+    ("mono", include_str!("../assets/themes/mono.toml")),
+];
+
+/// The colours of one player's snake.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnakeColours {
+    pub head: [f32; 3],
+    /// Colours spread evenly along the body from behind the head to the tail, blending into each
+    /// other in between. A single colour gives a plain body.
+    pub body: Vec<[f32; 3]>,
+}
+
+/// How the game looks, read from a TOML file like those in `assets/themes`. Every missing key keeps
+/// the look of the `classic` theme.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub background: [f32; 3],
+    /// One per player, reused from the start if there are more players than snakes.
+    pub snakes: Vec<SnakeColours>,
+    /// One colour per kind of food, keyed by names like `speed_up`. Kinds left out are drawn like
+    /// normal food.
+    pub food: BTreeMap<String, [f32; 3]>,
+    pub wall: [f32; 3],
+    /// The colour of the floor tiles, which are drawn a little smaller than a tile so the background
+    /// shows between them as grid lines. Without one there is no grid.
+    pub grid: Option<[f32; 3]>,
+    /// A sprite sheet in the assets folder to draw snakes with, laid out as described by
+    /// `sprites::Tile` and tinted in the colours above. Snakes are drawn as squares without one, or
+    /// if it is missing. Unlike the colours, it is only read when the game starts.
+    pub snake_sheet: Option<PathBuf>,
+    /// The width and height of a tile of the sprite sheet, in pixels.
+    pub snake_sheet_tile: u32,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: [0.0, 0.0, 0.0],
+            snakes: vec![
+                SnakeColours {
+                    head: [1.0, 0.0, 0.0],
+                    body: vec![[1.0, 0.0, 0.0]],
+                },
+                SnakeColours {
+                    head: [0.0, 0.6, 1.0],
+                    body: vec![[0.0, 0.6, 1.0]],
+                },
+            ],
+            food: [
+                ("normal", [1.0, 1.0, 0.0]),
+                ("bonus", [1.0, 0.6, 0.0]),
+                ("feast", [0.2, 0.9, 0.2]),
+                ("shrink", [0.6, 0.3, 0.1]),
+                ("speed_up", [0.0, 1.0, 1.0]),
+                ("slow_down", [0.3, 0.3, 1.0]),
+                ("ghost", [0.9, 0.9, 0.9]),
+            ]
+            .iter()
+            .map(|&(name, colour)| (name.to_owned(), colour))
+            .collect(),
+            wall: [0.5, 0.5, 0.5],
+            grid: None,
+            snake_sheet: Some(PathBuf::from("textures/snake.png")),
+            snake_sheet_tile: 32,
+        }
+    }
+}
+
+fn invalid(error: impl ToString) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+fn blend(from: [f32; 3], to: [f32; 3], amount: f32) -> [f32; 3] {
+    [
+        from[0] + (to[0] - from[0]) * amount,
+        from[1] + (to[1] - from[1]) * amount,
+        from[2] + (to[2] - from[2]) * amount,
+    ]
+}
+
+impl Theme {
+    /// The names accepted by `built_in`.
+    pub fn built_in_names() -> impl Iterator<Item = &'static str> {
+        std::iter::once("classic").chain(BUILT_IN.iter().map(|&(name, _)| name))
+    }
+
+    pub fn built_in(name: &str) -> Option<Theme> {
+        if name == "classic" {
+            return Some(Theme::default());
+        }
+
+        BUILT_IN
+            .iter()
+            .find(|&&(built_in, _)| built_in == name)
+            .map(|&(_, source)| toml::from_str(source).expect("Built-in themes are valid"))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        toml::from_str(&fs::read_to_string(path)?).map_err(invalid)
+    }
+
+    pub fn food_colour(&self, kind: FoodKind) -> [f32; 3] {
+        self.food
+            .get(&name(kind))
+            .or_else(|| self.food.get("normal"))
+            .copied()
+            .unwrap_or([1.0, 1.0, 0.0])
+    }
+
+    pub fn snake_colours(&self, player: usize) -> SnakeColours {
+        self.snakes
+            .iter()
+            .cycle()
+            .nth(player)
+            .cloned()
+            .unwrap_or(SnakeColours {
+                head: [1.0, 0.0, 0.0],
+                body: vec![[1.0, 0.0, 0.0]],
+            })
+    }
+
+    /// The colour of a player's body `along` the way from behind the head, at 0, to the tail, at 1.
+    pub fn body_colour(&self, player: usize, along: f32) -> [f32; 3] {
+        let colours = self.snake_colours(player);
+        let stops = match colours.body.len() {
+            0 => return colours.head,
+            length => length,
+        };
+
+        let position = along.clamp(0.0, 1.0) * (stops - 1) as f32;
+        let index = (position.floor() as usize).min(stops - 1);
+        let next = (index + 1).min(stops - 1);
+
+        blend(
+            colours.body[index],
+            colours.body[next],
+            position - index as f32,
+        )
+    }
+
+    /// The colour of the segment at `index` of a snake `length` segments long, head first.
+    pub fn segment_colour(&self, player: usize, index: usize, length: usize) -> [f32; 3] {
+        match index {
+            0 => self.snake_colours(player).head,
+            _ => self.body_colour(player, along_body(index, length)),
+        }
+    }
+
+    /// A colour faded most of the way into the background, as snakes are drawn once they have died
+    /// and no longer block anyone.
+    pub fn faded(&self, colour: [f32; 3]) -> [f32; 3] {
+        blend(colour, self.background, 0.7)
+    }
+}
+
+/// How far along the body a segment behind the head is, from 0 behind the head to 1 at the tail.
+pub fn along_body(index: usize, length: usize) -> f32 {
+    index.saturating_sub(1) as f32 / length.saturating_sub(2).max(1) as f32
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Notices when a theme file is changed on disk, so it can be reloaded while the game runs.
+pub struct ThemeWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ThemeWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+
+        ThemeWatcher { path, modified }
+    }
+
+    /// The theme as it is now, if the file changed since it was last looked at.
+    pub fn poll(&mut self) -> Option<io::Result<Theme>> {
+        let modified = modified(&self.path);
+
+        if modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+        Some(Theme::load(&self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_built_in_theme_parses() {
+        for (name, source) in BUILT_IN.iter() {
+            if let Err(error) = toml::from_str::<Theme>(source) {
+                panic!("The {} theme is invalid: {}", name, error);
+            }
+        }
+
+        for name in Theme::built_in_names() {
+            assert!(Theme::built_in(name).is_some(), "{} is missing", name);
+        }
+        assert_eq!(Theme::built_in("classic"), Some(Theme::default()));
+        assert_eq!(Theme::built_in("plaid"), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Theme>("wall = [0.1, 0.2, 0.3]").is_ok());
+        assert!(toml::from_str::<Theme>("walls = [0.1, 0.2, 0.3]").is_err());
+        assert!(toml::from_str::<Theme>(
+            "[[snakes]]\nhead = [1.0, 1.0, 1.0]\nbody = []\ntail = [0.0, 0.0, 0.0]"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn bodies_blend_from_the_first_colour_to_the_last() {
+        let theme = Theme {
+            snakes: vec![SnakeColours {
+                head: [1.0, 0.0, 0.0],
+                body: vec![[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(theme.segment_colour(0, 0, 5), [1.0, 0.0, 0.0]);
+        assert_eq!(theme.segment_colour(0, 1, 5), [0.0, 0.0, 0.0]);
+        assert_eq!(theme.segment_colour(0, 4, 5), [0.0, 0.0, 1.0]);
+        // Halfway along the body lands on the middle colour, and between stops they blend.
+        assert_eq!(theme.segment_colour(0, 2, 4), [0.0, 1.0, 0.0]);
+        assert_eq!(theme.body_colour(0, 0.25), [0.0, 0.5, 0.0]);
+        // Every player without colours of their own reuses those of the earlier ones.
+        assert_eq!(theme.segment_colour(3, 4, 5), [0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,398 @@
+use std::path::PathBuf;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    client::Spectator, AppState, Bot, Connection, DeathCause, Difficulty, GameResults, GameRules,
+    GameState, HighScores, InputBinding, Player, ReplayPlayer, Replays, Score, SnakeAction,
+    SnakeConfig, TickInterval,
+};
+
+struct HudText;
+
+/// The text shown in the middle of the window by the menu, pause and game over screens.
+struct ScreenText;
+
+struct HighScoreTable {
+    path: Option<PathBuf>,
+    scores: HighScores,
+}
+
+fn text_bundle(font: Handle<Font>, position: Rect<Val>, visible: bool) -> TextBundle {
+    TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position,
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font,
+                font_size: 30.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        visible: Visible {
+            is_visible: visible,
+            is_transparent: true,
+        },
+        ..Default::default()
+    }
+}
+
+fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    let font = asset_server.load("fonts/DejaVuSans.ttf");
+
+    commands
+        .spawn_bundle(text_bundle(
+            font.clone(),
+            Rect {
+                top: Val::Px(5.0),
+                left: Val::Px(10.0),
+                ..Default::default()
+            },
+            true,
+        ))
+        .insert(HudText);
+
+    commands
+        .spawn_bundle(text_bundle(
+            font,
+            Rect {
+                top: Val::Percent(25.0),
+                left: Val::Percent(15.0),
+                ..Default::default()
+            },
+            false,
+        ))
+        .insert(ScreenText);
+
+    // A table that fails to load is never saved over, so the scores already in it aren't lost.
+    let (path, scores) = match HighScores::default_path() {
+        Some(path) => match HighScores::load(&path) {
+            Ok(scores) => (Some(path), scores),
+            Err(error) => {
+                error!(
+                    "Failed to load high scores from {}, so new ones won't be saved: {}",
+                    path.display(),
+                    error
+                );
+                (None, HighScores::default())
+            }
+        },
+        None => (None, HighScores::default()),
+    };
+
+    commands.insert_resource(HighScoreTable { path, scores });
+}
+
+/// Every snake, best first, with the one being followed marked.
+fn scoreboard(spectator: &Spectator, score: &Score, game: &GameState) -> String {
+    let mut players = (0..game.snakes.len()).collect::<Vec<usize>>();
+    players.sort_by_key(|&player| std::cmp::Reverse(score.eaten.get(player).copied().unwrap_or(0)));
+
+    let mut contents = String::from("Scoreboard\n");
+
+    for (rank, player) in players.into_iter().enumerate() {
+        let snake = &game.snakes[player];
+        let name = match spectator.names.get(player) {
+            Some(Some(name)) => name.clone(),
+            _ => format!("Player {}", player + 1),
+        };
+
+        contents.push_str(&format!(
+            "{}{}. {}  {}  {}\n",
+            if spectator.following == Some(player) {
+                "> "
+            } else {
+                ""
+            },
+            rank + 1,
+            name,
+            score.eaten.get(player).copied().unwrap_or(0),
+            match snake.death {
+                Some(DeathCause::Left) => String::from("left"),
+                Some(_) => String::from("dead"),
+                None => format!("length {}", snake.segments.len()),
+            }
+        ));
+    }
+
+    contents
+}
+
+fn update_hud(
+    score: Res<Score>,
+    game: Res<GameState>,
+    interval: Option<Res<TickInterval>>,
+    spectator: Option<Res<Spectator>>,
+    mut texts: Query<&mut Text, With<HudText>>,
+) {
+    if let Some(spectator) = spectator {
+        let contents = scoreboard(&spectator, &score, &game);
+
+        for mut text in texts.iter_mut() {
+            text.sections[0].value = contents.clone();
+        }
+
+        return;
+    }
+
+    let mut contents = String::new();
+
+    for (index, snake) in game.snakes.iter().enumerate() {
+        if game.snakes.len() > 1 {
+            contents.push_str(&format!("P{} ", index + 1));
+        }
+
+        contents.push_str(&format!(
+            "Score: {}  Length: {}  ",
+            score.eaten.get(index).copied().unwrap_or(0),
+            snake.segments.len()
+        ));
+    }
+
+    contents.push_str(&format!("Time: {:.0}s", score.elapsed));
+
+    if let Some(interval) = interval {
+        contents.push_str(&format!("  Speed: {:.1}/s", 1.0 / interval.current));
+    }
+
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = contents.clone();
+    }
+}
+
+fn show_screen(texts: &mut Query<(&mut Text, &mut Visible), With<ScreenText>>, contents: String) {
+    for (mut text, mut visible) in texts.iter_mut() {
+        text.sections[0].value = contents.clone();
+        visible.is_visible = true;
+    }
+}
+
+fn hide_screen(mut visibilities: Query<&mut Visible, With<ScreenText>>) {
+    for mut visible in visibilities.iter_mut() {
+        visible.is_visible = false;
+    }
+}
+
+/// The menu screen, offering a choice of difficulty when the game is played out locally. Games on a
+/// server can't be paused, so pausing is only offered when `online` is false.
+fn menu_text(
+    spectator: bool,
+    online: bool,
+    players: usize,
+    difficulty: Option<Difficulty>,
+) -> String {
+    let controls = match (spectator, players) {
+        (true, _) => {
+            "Tab or the arrow keys to follow a snake,\n1-9 to pick one, F for the whole arena"
+        }
+        (false, 0) => "Sit back and watch the bots",
+        (false, 1) => "Arrow keys to move",
+        (false, _) => "Player 1: arrow keys, Player 2: WASD",
+    };
+
+    let pause = match (spectator || online, players) {
+        (true, _) => "",
+        (false, 0 | 1) => ", P to pause",
+        (false, _) => "\nP to pause",
+    };
+
+    let difficulty = match difficulty {
+        Some(difficulty) => format!(
+            "Difficulty: < {} >\nLeft and Right to change\n\n",
+            difficulty.name()
+        ),
+        None => String::new(),
+    };
+
+    format!(
+        "Snake!\n\n{}{}\n\n{}Press Space to start",
+        controls, pause, difficulty
+    )
+}
+
+/// The server or replay the game comes from, if it isn't simulated here from the config.
+#[derive(SystemParam)]
+pub struct GameSource<'a> {
+    connection: Option<Res<'a, Connection>>,
+    replays: Replays<'a>,
+}
+
+impl<'a> GameSource<'a> {
+    /// Whether the game is simulated here from the config, rather than by a server or a replay.
+    fn is_local(&self) -> bool {
+        self.connection.is_none() && self.replays.player.is_none()
+    }
+}
+
+fn show_menu(
+    config: Res<SnakeConfig>,
+    source: GameSource,
+    spectator: Option<Res<Spectator>>,
+    bindings: Query<&InputBinding>,
+    mut texts: Query<(&mut Text, &mut Visible), With<ScreenText>>,
+) {
+    let difficulty = Some(config.difficulty).filter(|_| source.is_local());
+
+    show_screen(
+        &mut texts,
+        menu_text(
+            spectator.is_some(),
+            source.connection.is_some(),
+            bindings.iter().count(),
+            difficulty,
+        ),
+    );
+}
+
+fn choose_difficulty(
+    mut input: ResMut<Input<KeyCode>>,
+    mut config: ResMut<SnakeConfig>,
+    mut rules: ResMut<GameRules>,
+    mut game: ResMut<GameState>,
+    mut source: GameSource,
+    bindings: Query<&InputBinding>,
+    mut texts: Query<(&mut Text, &mut Visible), With<ScreenText>>,
+) {
+    if !source.is_local() {
+        return;
+    }
+
+    let offset = match (
+        input.just_pressed(KeyCode::Left),
+        input.just_pressed(KeyCode::Right),
+    ) {
+        (true, false) => Difficulty::ALL.len() - 1,
+        (false, true) => 1,
+        _ => return,
+    };
+
+    input.reset(KeyCode::Left);
+    input.reset(KeyCode::Right);
+
+    let index = Difficulty::ALL
+        .iter()
+        .position(|&difficulty| difficulty == config.difficulty)
+        .unwrap_or(0);
+    let difficulty = Difficulty::ALL[(index + offset) % Difficulty::ALL.len()];
+
+    config.difficulty = difficulty;
+    config.speed_curve = None;
+    rules.speed = difficulty.speed_curve();
+    game.rules.speed = rules.speed;
+
+    if let Some(recorder) = &mut source.replays.recorder {
+        recorder.replay.rules.speed = rules.speed;
+    }
+
+    show_screen(
+        &mut texts,
+        menu_text(false, false, bindings.iter().count(), Some(difficulty)),
+    );
+}
+
+fn show_pause(mut texts: Query<(&mut Text, &mut Visible), With<ScreenText>>) {
+    show_screen(&mut texts, String::from("Paused\n\nPress P to resume"));
+}
+
+fn show_game_over(
+    config: Res<SnakeConfig>,
+    score: Res<Score>,
+    game: Res<GameState>,
+    mut table: ResMut<HighScoreTable>,
+    replay: Option<Res<ReplayPlayer>>,
+    bots: Query<&Player, With<Bot>>,
+    mut texts: Query<(&mut Text, &mut Visible), With<ScreenText>>,
+) {
+    let results = GameResults::new(&config, &game, &score.eaten, |index| {
+        bots.iter().any(|player| player.0 == index)
+    });
+
+    // A replay was scored when it was recorded, so watching it again only shows the table.
+    if replay.is_none() {
+        results.record(&mut table.scores);
+
+        if let Some(path) = &table.path {
+            if let Err(error) = table.scores.save(path) {
+                error!(
+                    "Failed to save high scores to {}: {}",
+                    path.display(),
+                    error
+                );
+            }
+        }
+    }
+
+    let mut contents = results.summary();
+    contents.push_str(&results.table(&table.scores));
+    contents.push_str("\nPress Space to restart");
+    show_screen(&mut texts, contents);
+}
+
+fn start_on_space(mut input: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if input.just_pressed(KeyCode::Space) {
+        state.set(AppState::Playing).unwrap();
+        // Keeps the same key press from being seen again before the state changes.
+        input.reset(KeyCode::Space);
+    }
+}
+
+fn pause(mut input: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if input.just_pressed(KeyCode::P) || input.just_pressed(KeyCode::Escape) {
+        // Fails harmlessly if the snake died on this frame, as the game over screen takes priority.
+        let _ = state.push(AppState::Paused);
+        input.reset(KeyCode::P);
+        input.reset(KeyCode::Escape);
+    }
+}
+
+fn resume(mut input: ResMut<Input<KeyCode>>, mut state: ResMut<State<AppState>>) {
+    if input.just_pressed(KeyCode::P) || input.just_pressed(KeyCode::Escape) {
+        state.pop().unwrap();
+        input.reset(KeyCode::P);
+        input.reset(KeyCode::Escape);
+    }
+}
+
+/// The HUD and the menu, pause and game over screens.
+pub(crate) struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(setup_ui.system())
+            .add_system(update_hud.system())
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(show_menu.system()))
+            .add_system_set(
+                SystemSet::on_update(AppState::Menu)
+                    .with_system(start_on_space.system())
+                    .with_system(choose_difficulty.system()),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(hide_screen.system()))
+            .add_system_set(
+                SystemSet::on_enter(AppState::GameOver).with_system(show_game_over.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::GameOver).with_system(start_on_space.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::GameOver).with_system(hide_screen.system()),
+            );
+
+        // A server keeps moving the snakes whatever we do, so its games can't be paused.
+        if app.world().get_resource::<Connection>().is_none() {
+            app.add_system_set(
+                SystemSet::on_update(AppState::Playing)
+                    .with_system(pause.system().after(SnakeAction::GameOver)),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(show_pause.system()))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(resume.system()))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(hide_screen.system()));
+        }
+    }
+}